@@ -0,0 +1,527 @@
+//! Tokio based writer that accumulates points and flushes them in batches.
+
+use super::{
+    error::WriteError,
+    retry::{random_duration, DropReason, Dropped, RetryBuffer, RetryPolicy},
+    Batch, Point,
+};
+use futures_sink::Sink;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_util::sync::PollSender;
+
+/// When [`AsyncBatchWriter`] flushes and how many points it can queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterConfig {
+    /// Flush when batch has this many points.
+    pub max_points: usize,
+    /// Flush before encoded batch would be longer than this many bytes. Point that is longer
+    /// alone is flushed in its own batch.
+    pub max_bytes: usize,
+    /// Flush at least this often.
+    pub flush_interval: Duration,
+    /// Random time up to `jitter` added to every `flush_interval`, so many writers started
+    /// together don't flush at the same time.
+    pub jitter: Duration,
+    /// Number of points waiting in queue before [`AsyncBatchWriter::write`] waits.
+    pub queue_size: usize,
+    /// How failed batches are repeated. Default repeats nothing.
+    pub retry: RetryPolicy,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self {
+            max_points: 5000,
+            max_bytes: 10 * 1024 * 1024,
+            flush_interval: Duration::from_secs(1),
+            jitter: Duration::from_millis(0),
+            queue_size: 10_000,
+            retry: RetryPolicy::none(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Point(Point),
+    Flush(oneshot::Sender<Result<(), WriteError>>),
+    Shutdown(oneshot::Sender<Result<(), WriteError>>),
+}
+
+/// Cloneable handle accepting points for a background batching task.
+///
+/// Points are passed to `flush` function in [`Batch`]es. `flush` is awaited before next point is
+/// taken from queue, so slow writes fill the bounded queue and [`write`](AsyncBatchWriter::write)
+/// waits (backpressure). Failed `flush` returns the batch back with error, and the batch is
+/// kept for next attempt according to [`WriterConfig::retry`].
+#[derive(Debug, Clone)]
+pub struct AsyncBatchWriter {
+    tx: mpsc::Sender<Command>,
+}
+
+impl AsyncBatchWriter {
+    /// Spawn background task on current tokio runtime.
+    ///
+    /// Batches that are given up are passed to `on_error`. Errors of
+    /// [`flush`](AsyncBatchWriter::flush) and [`shutdown`](AsyncBatchWriter::shutdown) are
+    /// returned to the caller too.
+    pub fn spawn<F, Fut, E>(config: WriterConfig, flush: F, on_error: E) -> (Self, JoinHandle<()>)
+    where
+        F: FnMut(Batch) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), (WriteError, Batch)>> + Send + 'static,
+        E: FnMut(Dropped) + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+        let task = Task {
+            config,
+            flush,
+            on_error,
+            batch: Batch::with_capacity(config.max_points),
+            batch_bytes: 0,
+            retry: RetryBuffer::new(config.retry),
+        };
+        let handle = tokio::spawn(task.run(rx));
+        (Self { tx }, handle)
+    }
+
+    /// Queue point. Waits if queue is full.
+    pub async fn write(&self, point: impl Into<Point>) -> Result<(), WriteError> {
+        self.tx
+            .send(Command::Point(point.into()))
+            .await
+            .map_err(|_| WriteError::Closed)
+    }
+
+    /// Queue point or return it back if queue is full or writer is closed.
+    pub fn try_write(&self, point: impl Into<Point>) -> Result<(), Point> {
+        self.tx
+            .try_send(Command::Point(point.into()))
+            .map_err(|err| match err {
+                mpsc::error::TrySendError::Full(Command::Point(p))
+                | mpsc::error::TrySendError::Closed(Command::Point(p)) => p,
+                _ => unreachable!("point command was sent"),
+            })
+    }
+
+    /// Flush all points queued before this call.
+    pub async fn flush(&self) -> Result<(), WriteError> {
+        self.request(Command::Flush).await
+    }
+
+    /// Flush all queued points and stop background task. Other handles are closed too.
+    pub async fn shutdown(&self) -> Result<(), WriteError> {
+        self.request(Command::Shutdown).await
+    }
+
+    /// Handle implementing [`Sink`] of points.
+    pub fn into_sink(self) -> AsyncBatchWriterSink {
+        AsyncBatchWriterSink {
+            sender: PollSender::new(self.tx.clone()),
+            writer: self,
+            flush: None,
+        }
+    }
+
+    async fn request<C>(&self, command: C) -> Result<(), WriteError>
+    where
+        C: FnOnce(oneshot::Sender<Result<(), WriteError>>) -> Command,
+    {
+        let (reply, response) = oneshot::channel();
+        self.tx
+            .send(command(reply))
+            .await
+            .map_err(|_| WriteError::Closed)?;
+        response.await.map_err(|_| WriteError::Closed)?
+    }
+}
+
+type FlushFuture = Pin<Box<dyn Future<Output = Result<(), WriteError>> + Send>>;
+
+/// [`Sink`] returned by [`AsyncBatchWriter::into_sink`].
+///
+/// Flushing the sink flushes the writer like [`AsyncBatchWriter::flush`]. Closing it flushes and
+/// closes only this handle, other handles keep working.
+pub struct AsyncBatchWriterSink {
+    writer: AsyncBatchWriter,
+    sender: PollSender<Command>,
+    flush: Option<FlushFuture>,
+}
+
+impl AsyncBatchWriterSink {
+    /// Writer this sink sends to.
+    pub fn get_ref(&self) -> &AsyncBatchWriter {
+        &self.writer
+    }
+}
+
+impl std::fmt::Debug for AsyncBatchWriterSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncBatchWriterSink")
+            .field("writer", &self.writer)
+            .finish()
+    }
+}
+
+impl Sink<Point> for AsyncBatchWriterSink {
+    type Error = WriteError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut()
+            .sender
+            .poll_reserve(cx)
+            .map_err(|_| WriteError::Closed)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Point) -> Result<(), Self::Error> {
+        self.get_mut()
+            .sender
+            .send_item(Command::Point(item))
+            .map_err(|_| WriteError::Closed)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let writer = this.writer.clone();
+        let flush = this
+            .flush
+            .get_or_insert_with(|| Box::pin(async move { writer.flush().await }));
+        let result = match flush.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.flush = None;
+        Poll::Ready(result)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let result = match Pin::new(&mut *this).poll_flush(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.sender.close();
+        Poll::Ready(result)
+    }
+}
+
+struct Task<F, E> {
+    config: WriterConfig,
+    flush: F,
+    on_error: E,
+    batch: Batch,
+    batch_bytes: usize,
+    retry: RetryBuffer,
+}
+
+impl<F, Fut, E> Task<F, E>
+where
+    F: FnMut(Batch) -> Fut,
+    Fut: Future<Output = Result<(), (WriteError, Batch)>>,
+    E: FnMut(Dropped),
+{
+    async fn run(mut self, mut rx: mpsc::Receiver<Command>) {
+        let mut deadline = self.next_deadline();
+        let mut shutdown = vec![];
+        loop {
+            let command = tokio::select! {
+                command = rx.recv() => command,
+                _ = time::sleep_until(deadline) => {
+                    self.flush_and_report().await;
+                    deadline = self.next_deadline();
+                    continue;
+                }
+                _ = time::sleep_until(self.next_retry()), if !self.retry.is_empty() => {
+                    self.retry_due().await;
+                    continue;
+                }
+            };
+            match command {
+                Some(Command::Point(point)) => {
+                    let bytes = point.encoded_len(None, &[]) + 1;
+                    if !self.batch.is_empty() && self.batch_bytes + bytes > self.config.max_bytes {
+                        self.flush_and_report().await;
+                        deadline = self.next_deadline();
+                    }
+                    self.batch_bytes += bytes;
+                    self.batch.push_point(point);
+                    if self.batch.len() >= self.config.max_points
+                        || self.batch_bytes >= self.config.max_bytes
+                    {
+                        self.flush_and_report().await;
+                        deadline = self.next_deadline();
+                    }
+                }
+                Some(Command::Flush(reply)) => {
+                    let _ = reply.send(self.flush().await);
+                    deadline = self.next_deadline();
+                }
+                Some(Command::Shutdown(reply)) => {
+                    // stop accepting new commands, but take everything that is already queued
+                    rx.close();
+                    shutdown.push(reply);
+                }
+                None => break,
+            }
+        }
+
+        let mut result = self.flush().await;
+        for reply in shutdown {
+            // only the first caller gets the error
+            let _ = reply.send(std::mem::replace(&mut result, Ok(())));
+        }
+        // the last chance for batches waiting for retry
+        let entries = self.retry.drain().collect::<Vec<_>>();
+        for mut entry in entries {
+            let batch = std::mem::take(&mut entry.batch);
+            if let Err((error, batch)) = (self.flush)(batch).await {
+                (self.on_error)(Dropped {
+                    batch,
+                    error,
+                    reason: DropReason::Shutdown,
+                });
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), WriteError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        self.batch_bytes = 0;
+        match (self.flush)(self.batch.clone_and_clear()).await {
+            Ok(()) => Ok(()),
+            Err((error, batch)) => {
+                let now = Instant::now().into_std();
+                for dropped in self.retry.push(batch, error.clone(), now) {
+                    (self.on_error)(dropped);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn flush_and_report(&mut self) {
+        // failed batch is already retried or reported
+        let _ = self.flush().await;
+    }
+
+    async fn retry_due(&mut self) {
+        while let Some(mut entry) = self.retry.pop_due(Instant::now().into_std()) {
+            let batch = std::mem::take(&mut entry.batch);
+            if let Err((error, batch)) = (self.flush)(batch).await {
+                entry.batch = batch;
+                let now = Instant::now().into_std();
+                for dropped in self.retry.retry_failed(entry, error, now) {
+                    (self.on_error)(dropped);
+                }
+            }
+        }
+    }
+
+    fn next_retry(&self) -> Instant {
+        self.retry
+            .next_attempt()
+            .map_or_else(Instant::now, Instant::from_std)
+    }
+
+    fn next_deadline(&self) -> Instant {
+        Instant::now() + self.config.flush_interval + random_duration(self.config.jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::point;
+    use std::sync::{Arc, Mutex};
+
+    type Flushed = Arc<Mutex<Vec<usize>>>;
+
+    fn spawn(config: WriterConfig) -> (AsyncBatchWriter, JoinHandle<()>, Flushed) {
+        let flushed = Flushed::default();
+        let sink = flushed.clone();
+        let (writer, handle) = AsyncBatchWriter::spawn(
+            config,
+            move |batch: Batch| {
+                sink.lock().unwrap().push(batch.len());
+                async { Ok(()) }
+            },
+            |dropped| panic!("{}", dropped.error),
+        );
+        (writer, handle, flushed)
+    }
+
+    #[tokio::test]
+    async fn flush_on_max_points_and_shutdown() {
+        let config = WriterConfig {
+            max_points: 3,
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let (writer, handle, flushed) = spawn(config);
+        let other = writer.clone();
+        for i in 0..7 {
+            other.write(point(i)).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+        handle.await.unwrap();
+
+        assert_eq!(*flushed.lock().unwrap(), vec![3, 3, 1]);
+        assert!(matches!(
+            other.write(point(8)).await,
+            Err(WriteError::Closed)
+        ));
+        assert!(other.try_write(point(9)).is_err());
+    }
+
+    #[tokio::test]
+    async fn flush_on_max_bytes() {
+        let config = WriterConfig {
+            // every line has 9 bytes with new line
+            max_bytes: 25,
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let (writer, handle, flushed) = spawn(config);
+        for i in 0..6 {
+            writer.write(point(i)).await.unwrap();
+        }
+        // longer than max_bytes alone
+        let long = Point::builder("m")
+            .unwrap()
+            .try_add_field(("v", "x".repeat(30).as_str()))
+            .build()
+            .unwrap();
+        writer.write(long).await.unwrap();
+        writer.write(point(7)).await.unwrap();
+        writer.flush().await.unwrap();
+        writer.shutdown().await.unwrap();
+        handle.await.unwrap();
+        assert_eq!(*flushed.lock().unwrap(), vec![2, 2, 2, 1, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flush_on_interval() {
+        let config = WriterConfig {
+            flush_interval: Duration::from_secs(10),
+            jitter: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let (writer, handle, flushed) = spawn(config);
+        writer.write(point(1)).await.unwrap();
+        writer.write(point(2)).await.unwrap();
+        time::sleep(Duration::from_secs(5)).await;
+        assert!(flushed.lock().unwrap().is_empty());
+
+        time::sleep(Duration::from_secs(7)).await;
+        assert_eq!(*flushed.lock().unwrap(), vec![2]);
+
+        writer.shutdown().await.unwrap();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn flush_error() {
+        let errors = Arc::new(Mutex::new(0));
+        let counter = errors.clone();
+        let (writer, handle) = AsyncBatchWriter::spawn(
+            WriterConfig {
+                max_points: 1,
+                ..Default::default()
+            },
+            |batch| async { Err((WriteError::Transport("down".into()), batch)) },
+            move |dropped: Dropped| {
+                assert_eq!(dropped.reason, DropReason::MaxAttempts);
+                *counter.lock().unwrap() += 1
+            },
+        );
+        writer.write(point(1)).await.unwrap();
+        writer.write(point(2)).await.unwrap();
+        writer.shutdown().await.unwrap();
+        handle.await.unwrap();
+        assert_eq!(*errors.lock().unwrap(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_failed_batches() {
+        let attempts = Arc::new(Mutex::new(vec![]));
+        let log = attempts.clone();
+        let dropped = Arc::new(Mutex::new(vec![]));
+        let sink = dropped.clone();
+        let config = WriterConfig {
+            max_points: 2,
+            flush_interval: Duration::from_secs(3600),
+            retry: RetryPolicy {
+                initial_backoff: Duration::from_secs(1),
+                jitter: Duration::from_secs(0),
+                max_attempts: 3,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (writer, handle) = AsyncBatchWriter::spawn(
+            config,
+            move |batch: Batch| {
+                let mut log = log.lock().unwrap();
+                log.push(batch.len());
+                // the first batch succeeds on the third attempt, the second never
+                let result = match log.len() {
+                    3 => Ok(()),
+                    _ => Err((WriteError::ServiceUnavailable { retry_after: None }, batch)),
+                };
+                async { result }
+            },
+            move |d: Dropped| sink.lock().unwrap().push((d.batch.len(), d.reason)),
+        );
+        writer.write(point(1)).await.unwrap();
+        writer.write(point(2)).await.unwrap();
+        time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(*attempts.lock().unwrap(), vec![2]);
+
+        // retried after 1 and 2 seconds
+        time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(*attempts.lock().unwrap(), vec![2, 2, 2]);
+
+        writer.write(point(3)).await.unwrap();
+        assert!(writer.flush().await.is_err());
+        time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(attempts.lock().unwrap().len(), 6);
+        assert_eq!(*dropped.lock().unwrap(), vec![(1, DropReason::MaxAttempts)]);
+
+        writer.shutdown().await.unwrap();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sink() {
+        use futures_util::SinkExt;
+
+        let (writer, handle, flushed) = spawn(WriterConfig {
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        });
+        let mut sink = writer.clone().into_sink();
+        sink.send(point(1)).await.unwrap();
+        sink.feed(point(2)).await.unwrap();
+        sink.feed(point(3)).await.unwrap();
+        sink.flush().await.unwrap();
+        assert_eq!(*flushed.lock().unwrap(), vec![1, 2]);
+
+        sink.close().await.unwrap();
+        assert!(matches!(sink.send(point(4)).await, Err(WriteError::Closed)));
+        writer.write(point(5)).await.unwrap();
+        writer.shutdown().await.unwrap();
+        handle.await.unwrap();
+        assert_eq!(*flushed.lock().unwrap(), vec![1, 2, 1]);
+    }
+}
@@ -1,4 +1,17 @@
-use super::{Point, Precision};
+use super::{
+    error::LineError, BatchStats, CompressedPayload, Compression, Measurement, Point, Precision,
+    Series, Tag, TagSet, TagValue,
+};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    io::{self, Write},
+    iter::FromIterator,
+    ops::RangeBounds,
+    slice, vec,
+};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 fn highest_precision(vec: &[Point]) -> Option<Precision> {
     debug_assert!(Precision::Nanos > Precision::Secs);
@@ -15,12 +28,37 @@ fn highest_precision(vec: &[Point]) -> Option<Precision> {
     })
 }
 
+/// Collect default tags sorted by key. The last tag with the same key wins.
+pub(crate) fn default_tag_set<I>(tags: I) -> TagSet
+where
+    I: IntoIterator,
+    I::Item: Into<Tag>,
+{
+    let mut tags = tags.into_iter().map(|t| t.into()).collect::<TagSet>();
+    tags.reverse();
+    tags.sort_by(|a, b| a.key().cmp(b.key()));
+    tags.dedup_by(|a, b| a.key() == b.key());
+    tags
+}
+
+/// Decide which value is kept when merged points contain the same field key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FieldConflict {
+    /// Keep value from the point that appears first in the batch.
+    KeepFirst,
+    /// Keep value from the point that appears last in the batch. This is how InfluxDB resolves
+    /// duplicated points.
+    #[default]
+    KeepLast,
+}
+
 /// A collection of data [`Points`] in InfluxDB line protocol format.
 ///
 /// [`Points`]:Point
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Batch {
     inner: Vec<Point>,
+    default_tags: TagSet,
 }
 
 impl<V> From<V> for Batch
@@ -50,9 +88,36 @@ impl Batch {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             inner: Vec::with_capacity(capacity),
+            default_tags: Default::default(),
         }
     }
 
+    /// Set tags that are added to every point in this batch (also called global tags).
+    ///
+    /// Default tags are merged into each point when batch is converted to line protocol. If
+    /// point has a tag with the same key, point tag wins.
+    pub fn with_default_tags<I>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Tag>,
+    {
+        self.set_default_tags(tags);
+        self
+    }
+
+    /// See [`with_default_tags`](Batch::with_default_tags).
+    pub fn set_default_tags<I>(&mut self, tags: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<Tag>,
+    {
+        self.default_tags = default_tag_set(tags);
+    }
+
+    pub fn default_tags(&self) -> &[Tag] {
+        &self.default_tags
+    }
+
     /// This will build batch in InlfuxDB line protocol format.
     ///
     /// If you specify `precision` that is less accurate than point timestamp precision stored inside Batch
@@ -60,15 +125,45 @@ impl Batch {
     pub fn to_line_protocol_lossy(&self, precision: Option<Precision>) -> String {
         self.inner
             .iter()
-            .map(|point| point.to_text_with_precision(precision))
+            .map(|point| {
+                if self.default_tags.is_empty() {
+                    point.to_text_with_precision(precision)
+                } else {
+                    point.to_text_with_default_tags(precision, &self.default_tags)
+                }
+            })
             .collect::<Vec<_>>()
             .join("\n")
     }
 
+    /// Encode batch (like [`to_line_protocol_lossy`]) and compress it on the fly.
+    ///
+    /// [`to_line_protocol_lossy`]:Batch::to_line_protocol_lossy
+    pub fn to_compressed(
+        &self,
+        compression: Compression,
+        precision: Option<Precision>,
+    ) -> io::Result<CompressedPayload> {
+        let mut writer = compression.writer(Vec::new())?;
+        let mut line = String::new();
+        for (i, point) in self.inner.iter().enumerate() {
+            line.clear();
+            if i > 0 {
+                line.push('\n');
+            }
+            point.write_text(&mut line, precision, &self.default_tags);
+            writer.write_all(line.as_bytes())?;
+        }
+        writer.finish_payload()
+    }
+
     pub fn clone_and_clear(&mut self) -> Self {
         let mut new_v = Vec::with_capacity(self.len());
         std::mem::swap(&mut self.inner, &mut new_v);
-        Self { inner: new_v }
+        Self {
+            inner: new_v,
+            default_tags: self.default_tags.clone(),
+        }
     }
 
     /// Get current precision.
@@ -97,12 +192,389 @@ impl Batch {
     pub fn len(&self) -> usize {
         self.inner.len()
     }
+
+    /// Iterate over points in order they were pushed.
+    pub fn iter(&self) -> slice::Iter<'_, Point> {
+        self.inner.iter()
+    }
+
+    /// Iterate over points allowing to modify them.
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, Point> {
+        self.inner.iter_mut()
+    }
+
+    /// Retains only the points specified by the predicate.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Point) -> bool,
+    {
+        self.inner.retain(f)
+    }
+
+    /// Removes the specified range of points from the batch and returns them as iterator.
+    pub fn drain<R>(&mut self, range: R) -> vec::Drain<'_, Point>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.inner.drain(range)
+    }
+
+    /// Sorts points with a key extraction function, keeping order of equal points.
+    pub fn sort_by_key<K, F>(&mut self, f: F)
+    where
+        F: FnMut(&Point) -> K,
+        K: Ord,
+    {
+        self.inner.sort_by_key(f)
+    }
+
+    /// Removes all but the first of consecutive points satisfying a given equality relation.
+    ///
+    /// See [`Vec::dedup_by`] for details about the order of arguments passed to `same_point`.
+    pub fn dedup_by<F>(&mut self, same_point: F)
+    where
+        F: FnMut(&mut Point, &mut Point) -> bool,
+    {
+        self.inner.dedup_by(same_point)
+    }
+
+    /// Retains only points with timestamp (in nanoseconds) inside `range`.
+    ///
+    /// # Note
+    /// Points with [`Timestamp::Now`] have no known time and are always removed.
+    ///
+    /// [`Timestamp::Now`]:crate::Timestamp::Now
+    pub fn retain_time_range<R>(&mut self, range: R)
+    where
+        R: RangeBounds<i64>,
+    {
+        self.inner.retain(|p| {
+            p.timestamp()
+                .timestamp_nanos()
+                .map(|ts| range.contains(&ts))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Keeps the first `len` points and drops the rest.
+    pub fn truncate(&mut self, len: usize) {
+        self.inner.truncate(len)
+    }
+
+    /// Split batch into sub-batches with the same [`Measurement`].
+    pub fn group_by_measurement(self) -> BTreeMap<Measurement, Batch> {
+        self.group_by(|p| p.measurement().clone())
+    }
+
+    /// Split batch into sub-batches with the same [`Series`].
+    ///
+    /// Series include default tags of batch, so they match written lines.
+    pub fn group_by_series(self) -> BTreeMap<Series, Batch> {
+        let default_tags = self.default_tags.clone();
+        self.group_by(|p| p.series_with_default_tags(&default_tags))
+    }
+
+    /// Split batch into sub-batches by value of tag `key`.
+    ///
+    /// Default tags of batch are used for points without tag `key`. Points without tag `key`
+    /// are grouped under `None`.
+    pub fn partition_by_tag(self, key: &str) -> BTreeMap<Option<TagValue>, Batch> {
+        let default_value = self
+            .default_tags
+            .iter()
+            .find(|t| AsRef::<str>::as_ref(t.key()) == key)
+            .map(|t| t.value().clone());
+        self.group_by(|p| p.tag_value(key).cloned().or_else(|| default_value.clone()))
+    }
+
+    /// Split batch into accepted points and points rejected by server with their errors.
+    ///
+    /// `errors` come from [`WriteError::line_errors`](crate::error::WriteError::line_errors) of
+    /// write of this whole batch encoded with `precision`. Errors are matched by line number, or
+    /// by encoded line when server did not send number. Errors matching no point are ignored.
+    pub fn split_rejected(
+        self,
+        errors: &[LineError],
+        precision: Option<Precision>,
+    ) -> (Batch, Vec<(Point, LineError)>) {
+        let Batch {
+            inner,
+            default_tags,
+        } = self;
+        let mut rejected = vec![None; inner.len()];
+        let mut by_text = vec![];
+        for error in errors {
+            match error.line.and_then(|l| rejected.get_mut(l.wrapping_sub(1))) {
+                Some(slot) => {
+                    slot.get_or_insert_with(|| error.clone());
+                }
+                None if error.original_line.is_some() => by_text.push(error),
+                None => {}
+            }
+        }
+        if !by_text.is_empty() {
+            for (point, slot) in inner.iter().zip(rejected.iter_mut()) {
+                if slot.is_some() {
+                    continue;
+                }
+                let line = point.to_text_with_default_tags(precision, &default_tags);
+                if let Some(i) = by_text
+                    .iter()
+                    .position(|e| e.original_line.as_deref().map(str::trim) == Some(&line))
+                {
+                    *slot = Some(by_text.remove(i).clone());
+                }
+            }
+        }
+
+        let mut accepted = Batch {
+            inner: Vec::with_capacity(inner.len()),
+            default_tags,
+        };
+        let mut bad = vec![];
+        for (point, error) in inner.into_iter().zip(rejected) {
+            match error {
+                Some(error) => bad.push((point, error)),
+                None => accepted.inner.push(point),
+            }
+        }
+        (accepted, bad)
+    }
+
+    /// Points with default tags added to their tag sets.
+    pub(crate) fn tagged_points(&self) -> impl Iterator<Item = Point> + '_ {
+        self.inner.iter().map(move |p| {
+            let mut p = p.clone();
+            p.add_default_tags(&self.default_tags);
+            p
+        })
+    }
+
+    /// Length in bytes of [`to_line_protocol_lossy(None)`](Batch::to_line_protocol_lossy)
+    /// output, computed without encoding points.
+    pub fn encoded_len(&self) -> usize {
+        let new_lines = self.len().saturating_sub(1);
+        self.inner
+            .iter()
+            .map(|p| p.encoded_len(None, &self.default_tags))
+            .sum::<usize>()
+            + new_lines
+    }
+
+    /// Compute statistics of this batch.
+    pub fn stats(&self) -> BatchStats {
+        let mut stats = BatchStats {
+            points: self.len(),
+            encoded_len: self.encoded_len(),
+            ..Default::default()
+        };
+        let mut series = HashSet::new();
+        for point in &self.inner {
+            series.insert(point.series_with_default_tags(&self.default_tags));
+
+            let fields = stats.fields.entry(point.measurement().clone()).or_default();
+            for field in point.fields() {
+                fields
+                    .entry(field.key().clone())
+                    .or_default()
+                    .insert(field.value().field_type());
+            }
+
+            if let Some(ts) = point.timestamp().timestamp_nanos() {
+                stats.min_timestamp = Some(stats.min_timestamp.map_or(ts, |min| min.min(ts)));
+                stats.max_timestamp = Some(stats.max_timestamp.map_or(ts, |max| max.max(ts)));
+            }
+            *stats.precisions.entry(point.precision()).or_default() += 1;
+        }
+        stats.series = series.len();
+        stats
+    }
+
+    /// Merge points with the same series and timestamp into a single point.
+    ///
+    /// Same as [`compact_with`] using [`FieldConflict::KeepLast`].
+    ///
+    /// [`compact_with`]:Batch::compact_with
+    pub fn compact(&mut self) {
+        self.compact_with(FieldConflict::default())
+    }
+
+    /// Merge points with the same series and timestamp into a single point.
+    ///
+    /// Series include default tags of batch. Timestamps are compared after normalization to
+    /// nanoseconds so `Secs(1)` and `Milli(1000)` are the same timestamp. Merged point keeps position and timestamp of
+    /// the first point. Duplicated field keys are resolved with `conflict`.
+    ///
+    /// # Note
+    /// Points with [`Timestamp::Now`] are never merged.
+    ///
+    /// [`Timestamp::Now`]:crate::Timestamp::Now
+    pub fn compact_with(&mut self, conflict: FieldConflict) {
+        let mut positions = HashMap::<(Series, i64), usize>::new();
+        let mut compacted = Vec::<Point>::with_capacity(self.len());
+        for point in self.inner.drain(..) {
+            let ts = match point.timestamp().timestamp_nanos() {
+                Some(ts) => ts,
+                None => {
+                    compacted.push(point);
+                    continue;
+                }
+            };
+            match positions.entry((point.series_with_default_tags(&self.default_tags), ts)) {
+                Entry::Occupied(e) => compacted[*e.get()].merge_fields(point, conflict),
+                Entry::Vacant(e) => {
+                    e.insert(compacted.len());
+                    compacted.push(point);
+                }
+            }
+        }
+        self.inner = compacted;
+    }
+
+    /// Same as [`to_line_protocol_lossy`] but points are encoded on rayon thread pool.
+    ///
+    /// [`to_line_protocol_lossy`]:Batch::to_line_protocol_lossy
+    #[cfg(feature = "rayon")]
+    pub fn to_line_protocol_par(&self, precision: Option<Precision>) -> String {
+        // big enough to make task overhead negligible
+        const CHUNK: usize = 1024;
+        let parts = self
+            .inner
+            .par_chunks(CHUNK)
+            .map(|points| self.encode(points, precision))
+            .collect::<Vec<_>>();
+        parts.join("\n")
+    }
+
+    /// Encode batch into payloads of whole lines that are at most `max_bytes` long, using rayon
+    /// thread pool. Payloads are returned in points order.
+    ///
+    /// Line longer than `max_bytes` is returned as a single payload.
+    #[cfg(feature = "rayon")]
+    pub fn chunks_by_bytes_par(
+        &self,
+        precision: Option<Precision>,
+        max_bytes: usize,
+    ) -> Vec<String> {
+        let lens = self
+            .inner
+            .par_iter()
+            .map(|p| p.encoded_len(precision, &self.default_tags))
+            .collect::<Vec<_>>();
+
+        let mut ranges = vec![];
+        let mut start = 0;
+        let mut chunk_len = 0;
+        for (i, len) in lens.into_iter().enumerate() {
+            // new line is needed before every line except the first one
+            if i > start && chunk_len + 1 + len > max_bytes {
+                ranges.push(start..i);
+                start = i;
+                chunk_len = len;
+            } else if i > start {
+                chunk_len += 1 + len;
+            } else {
+                chunk_len = len;
+            }
+        }
+        if start < self.len() {
+            ranges.push(start..self.len());
+        }
+
+        ranges
+            .into_par_iter()
+            .map(|range| self.encode(&self.inner[range], precision))
+            .collect()
+    }
+
+    #[cfg(feature = "rayon")]
+    fn encode(&self, points: &[Point], precision: Option<Precision>) -> String {
+        let mut out = String::new();
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            point.write_text(&mut out, precision, &self.default_tags);
+        }
+        out
+    }
+
+    fn group_by<K, F>(self, mut f: F) -> BTreeMap<K, Batch>
+    where
+        K: Ord,
+        F: FnMut(&Point) -> K,
+    {
+        let Batch {
+            inner,
+            default_tags,
+        } = self;
+        let mut groups = BTreeMap::<K, Batch>::new();
+        for point in inner {
+            groups
+                .entry(f(&point))
+                .or_insert_with(|| Batch {
+                    inner: vec![],
+                    default_tags: default_tags.clone(),
+                })
+                .push_point(point);
+        }
+        groups
+    }
+}
+
+impl IntoIterator for Batch {
+    type Item = Point;
+    type IntoIter = vec::IntoIter<Point>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Batch {
+    type Item = &'a Point;
+    type IntoIter = slice::Iter<'a, Point>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Batch {
+    type Item = &'a mut Point;
+    type IntoIter = slice::IterMut<'a, Point>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<P> FromIterator<P> for Batch
+where
+    P: Into<Point>,
+{
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        Self {
+            inner: iter.into_iter().map(|p| p.into()).collect(),
+            default_tags: Default::default(),
+        }
+    }
+}
+
+impl<P> Extend<P> for Batch
+where
+    P: Into<Point>,
+{
+    fn extend<I: IntoIterator<Item = P>>(&mut self, iter: I) {
+        self.inner.extend(iter.into_iter().map(|p| p.into()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::Timestamp;
+    use std::convert::TryFrom;
 
     #[test]
     fn precision_test() {
@@ -116,4 +588,284 @@ mod tests {
             Some(Precision::Nanos)
         );
     }
+
+    fn point(measurement: &str, timestamp: Timestamp) -> Point {
+        Point::builder(measurement)
+            .unwrap()
+            .try_add_field(("a", 1i64))
+            .timestamp(timestamp)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn collection_api_test() {
+        let mut b: Batch = (1..=5).map(|i| point("a", Timestamp::Secs(i))).collect();
+        b.extend(vec![point("b", Timestamp::Now)]);
+        assert_eq!(b.len(), 6);
+
+        b.retain_time_range(2 * 10i64.pow(9)..4 * 10i64.pow(9));
+        assert_eq!(
+            b.iter().map(|p| p.timestamp()).collect::<Vec<_>>(),
+            vec![Timestamp::Secs(2), Timestamp::Secs(3)]
+        );
+
+        b.sort_by_key(|p| std::cmp::Reverse(p.timestamp().timestamp_nanos()));
+        let drained = b.drain(..1).collect::<Vec<_>>();
+        assert_eq!(drained, vec![point("a", Timestamp::Secs(3))]);
+        assert_eq!(b.into_iter().count(), 1);
+    }
+
+    #[test]
+    fn group_by_test() {
+        let tagged = |m: &str, bucket: &str| {
+            Point::builder(m)
+                .unwrap()
+                .try_add_tag(("bucket", bucket))
+                .try_add_field(("a", 1i64))
+                .build()
+                .unwrap()
+        };
+        let b = Batch::from(vec![
+            tagged("cpu", "x"),
+            tagged("mem", "x"),
+            tagged("cpu", "y"),
+            tagged("cpu", "x"),
+            point("cpu", Timestamp::Now),
+        ]);
+
+        let by_measurement = b.clone().group_by_measurement();
+        assert_eq!(by_measurement.len(), 2);
+        assert_eq!(by_measurement[&Measurement::new("cpu").unwrap()].len(), 4);
+
+        let by_series = b.clone().group_by_series();
+        let keys = by_series.keys().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["cpu", "cpu,bucket=x", "cpu,bucket=y", "mem,bucket=x"]
+        );
+        assert_eq!(by_series.values().map(Batch::len).sum::<usize>(), 5);
+
+        let by_tag = b.partition_by_tag("bucket");
+        assert_eq!(by_tag[&None].len(), 1);
+        assert_eq!(by_tag[&Some(TagValue::new("x").unwrap())].len(), 3);
+        assert_eq!(by_tag[&Some(TagValue::new("y").unwrap())].len(), 1);
+    }
+
+    #[test]
+    fn compact_test() {
+        let line = |m: &str, field: (&str, i64), ts: Timestamp| {
+            Point::builder(m)
+                .unwrap()
+                .try_add_tag(("host", "a"))
+                .try_add_field(field)
+                .timestamp(ts)
+                .build()
+                .unwrap()
+        };
+        let b = Batch::from(vec![
+            line("cpu", ("user", 1), Timestamp::Secs(1)),
+            line("mem", ("used", 2), Timestamp::Secs(1)),
+            line("cpu", ("system", 3), Timestamp::Milli(1000)),
+            line("cpu", ("user", 4), Timestamp::Nanos(10i64.pow(9))),
+            line("cpu", ("user", 5), Timestamp::Secs(2)),
+            line("cpu", ("idle", 6), Timestamp::Now),
+            line("cpu", ("idle", 7), Timestamp::Now),
+        ]);
+
+        let mut last = b.clone();
+        last.compact();
+        assert_eq!(
+            last.to_line_protocol_lossy(None),
+            "cpu,host=a user=4i,system=3i 1\n\
+             mem,host=a used=2i 1\n\
+             cpu,host=a user=5i 2\n\
+             cpu,host=a idle=6i\n\
+             cpu,host=a idle=7i"
+        );
+
+        let mut first = b;
+        first.compact_with(FieldConflict::KeepFirst);
+        assert_eq!(first.len(), 5);
+        assert!(first
+            .to_line_protocol_lossy(None)
+            .starts_with("cpu,host=a user=1i,system=3i 1\n"));
+    }
+
+    #[test]
+    fn group_with_default_tags_test() {
+        let host = |v: &str| Tag::try_from(("host", v)).unwrap();
+        let tagged = |host: Option<&str>, field: (&str, i64)| {
+            let builder = Point::builder("cpu").unwrap();
+            let builder = match host {
+                Some(h) => builder.try_add_tag(("host", h)),
+                None => builder,
+            };
+            builder
+                .try_add_field(field)
+                .timestamp(Timestamp::Secs(1))
+                .build()
+                .unwrap()
+        };
+        // the first two points are written to the same series
+        let b = Batch::from(vec![
+            tagged(Some("a"), ("x", 1)),
+            tagged(None, ("y", 2)),
+            tagged(Some("b"), ("z", 3)),
+        ])
+        .with_default_tags(vec![host("a")]);
+
+        let by_series = b.clone().group_by_series();
+        let keys = by_series.keys().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["cpu,host=a", "cpu,host=b"]);
+        assert_eq!(
+            by_series.values().map(Batch::len).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+
+        let by_tag = b.clone().partition_by_tag("host");
+        assert!(!by_tag.contains_key(&None));
+        assert_eq!(by_tag[&Some(TagValue::new("a").unwrap())].len(), 2);
+        assert_eq!(b.stats().series, 2);
+
+        let mut compacted = b;
+        compacted.compact();
+        assert_eq!(
+            compacted.to_line_protocol_lossy(None),
+            "cpu,host=a x=1i,y=2i 1\ncpu,host=b z=3i 1"
+        );
+    }
+
+    #[test]
+    fn default_tags_test() {
+        let tag = |k: &str, v: &str| Tag::try_from((k, v)).unwrap();
+        let p = Point::builder("cpu")
+            .unwrap()
+            .try_add_tags(vec![("host", "local"), ("core", "0")])
+            .try_add_field(("a", 1i64))
+            .build()
+            .unwrap();
+        let b = Batch::from(vec![p, point("mem", Timestamp::Now)]).with_default_tags(vec![
+            tag("region", "eu"),
+            tag("host", "server"),
+            tag("region", "us"),
+        ]);
+
+        assert_eq!(
+            b.to_line_protocol_lossy(None),
+            "cpu,core=0,host=local,region=us a=1i\nmem,host=server,region=us a=1i"
+        );
+        assert_eq!(
+            b.group_by_measurement()
+                .values()
+                .map(|b| b.default_tags().len())
+                .collect::<Vec<_>>(),
+            vec![2, 2]
+        );
+    }
+
+    #[test]
+    fn stats_test() {
+        let p = Point::builder("cpu load")
+            .unwrap()
+            .try_add_tag(("host", "a=b"))
+            .try_add_fields(vec![("msg", r#"quote " and \"#)])
+            .try_add_field(("load", 0.5f64))
+            .timestamp(Timestamp::Milli(2000))
+            .build()
+            .unwrap();
+        let b = Batch::from(vec![
+            p,
+            point("cpu load", Timestamp::Secs(-1)),
+            point("cpu load", Timestamp::Now),
+            point("mem", Timestamp::Secs(5)),
+        ])
+        .with_default_tags(vec![Tag::try_from(("region", "eu west")).unwrap()]);
+
+        let stats = b.stats();
+        assert_eq!(stats.points, 4);
+        assert_eq!(stats.encoded_len, b.to_line_protocol_lossy(None).len());
+        assert_eq!(stats.series, 3);
+        assert_eq!(stats.measurements(), 2);
+        assert_eq!(
+            stats.fields[&Measurement::new("cpu load").unwrap()].len(),
+            3
+        );
+        assert_eq!(stats.min_timestamp, Some(-(10i64.pow(9))));
+        assert_eq!(stats.max_timestamp, Some(5 * 10i64.pow(9)));
+        assert_eq!(stats.precisions[&None], 1);
+        assert_eq!(stats.precisions[&Some(Precision::Secs)], 2);
+
+        assert_eq!(Batch::default().stats(), BatchStats::default());
+    }
+
+    #[test]
+    fn to_compressed_test() {
+        let b = (0..10)
+            .map(|i| point("a", Timestamp::Secs(i)))
+            .collect::<Batch>();
+        let payload = b.to_compressed(Compression::None, None).unwrap();
+        assert_eq!(
+            payload.as_bytes(),
+            b.to_line_protocol_lossy(None).as_bytes()
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_encoding_test() {
+        let b = (0..5000)
+            .map(|i| point(if i % 2 == 0 { "a" } else { "bb" }, Timestamp::Milli(i)))
+            .collect::<Batch>()
+            .with_default_tags(vec![Tag::try_from(("host", "x")).unwrap()]);
+        let text = b.to_line_protocol_lossy(Some(Precision::Secs));
+        assert_eq!(b.to_line_protocol_par(Some(Precision::Secs)), text);
+
+        let chunks = b.chunks_by_bytes_par(Some(Precision::Secs), 1000);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() <= 1000));
+        assert_eq!(chunks.join("\n"), text);
+
+        assert_eq!(b.chunks_by_bytes_par(None, 1).len(), 5000);
+        assert!(Batch::default().chunks_by_bytes_par(None, 10).is_empty());
+        assert_eq!(Batch::default().to_line_protocol_par(None), "");
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn split_rejected() {
+        use crate::error::WriteError;
+
+        let b = Batch::from(
+            (1..=4)
+                .map(|ts| point("m", Timestamp::Nanos(ts)))
+                .collect::<Vec<_>>(),
+        );
+        let lines = b.to_line_protocol_lossy(None);
+        let third = lines.lines().nth(2).unwrap();
+        let body = r#"{"error":"partial write","data":[{"line_number":2,"original_line":"x","error_message":"bad"},{"line_number":9,"error_message":"out of range"}]}"#;
+        let err = WriteError::from_response(400, body, None).unwrap();
+        let mut errors = err.line_errors().to_vec();
+        errors.push(LineError {
+            line: None,
+            original_line: Some(third.to_string()),
+            reason: "field type conflict".to_string(),
+        });
+
+        let (accepted, rejected) = b.split_rejected(&errors, None);
+        assert_eq!(
+            accepted.iter().map(|p| p.timestamp()).collect::<Vec<_>>(),
+            vec![Timestamp::Nanos(1), Timestamp::Nanos(4)]
+        );
+        assert_eq!(
+            rejected
+                .iter()
+                .map(|(p, e)| (p.timestamp(), e.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Timestamp::Nanos(2), "bad"),
+                (Timestamp::Nanos(3), "field type conflict")
+            ]
+        );
+    }
 }
@@ -0,0 +1,351 @@
+//! Synchronous client for InfluxDB v2 write API.
+
+use super::{
+    error::WriteError, Batch, CompressedPayload, Compression, Precision, RetryPolicy,
+    WriteRequestBuilder, WriteTarget,
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Blocking writer posting [`Batch`]es to `/api/v2/write`.
+///
+/// When server responds with `429` or `503` and `Retry-After` not longer than
+/// [`max_retry_after`](InfluxWriter::max_retry_after), writer waits and sends the same payload
+/// again (at most [`throttle_retries`](InfluxWriter::throttle_retries) times). With
+/// [`retry_policy`](InfluxWriter::retry_policy) all retryable failures are repeated by the policy
+/// instead.
+#[derive(Debug, Clone)]
+pub struct InfluxWriter {
+    agent: ureq::Agent,
+    builder: WriteRequestBuilder,
+    max_retry_after: Duration,
+    throttle_retries: usize,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl InfluxWriter {
+    pub fn new(target: WriteTarget) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self::with_agent(target, agent)
+    }
+
+    /// Use already configured `agent` (timeouts, proxy, TLS).
+    ///
+    /// Requests are sent with `http_status_as_error(false)` whatever the agent config is, so
+    /// error bodies and `Retry-After` are always read.
+    pub fn with_agent(target: WriteTarget, agent: ureq::Agent) -> Self {
+        Self {
+            agent,
+            builder: WriteRequestBuilder::new(target),
+            max_retry_after: Duration::from_secs(60),
+            throttle_retries: 1,
+            retry_policy: None,
+        }
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.builder = self.builder.compression(compression);
+        self
+    }
+
+    /// The longest `Retry-After` writer waits for. Default is 60 seconds.
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// How many times throttled write is repeated. Default is 1, 0 disables waiting.
+    pub fn throttle_retries(mut self, retries: usize) -> Self {
+        self.throttle_retries = retries;
+        self
+    }
+
+    /// Repeat writes failed with retryable error (see [`WriteError::is_retryable`]) by `policy`.
+    /// Calling thread sleeps between attempts.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    pub fn target(&self) -> &WriteTarget {
+        self.builder.target()
+    }
+
+    pub fn write(&self, batch: &Batch) -> Result<(), WriteError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let (payload, precision) = self.builder.encode(batch)?;
+        self.write_payload(payload, precision)
+    }
+
+    /// Write `payload` encoded with `precision`.
+    pub fn write_payload(
+        &self,
+        payload: CompressedPayload,
+        precision: Precision,
+    ) -> Result<(), WriteError> {
+        if let Some(policy) = &self.retry_policy {
+            return self.write_payload_with_policy(payload, precision, policy);
+        }
+        let mut attempt = 0;
+        loop {
+            let request = self.builder.build_payload(payload.clone(), precision)?;
+            match self.send(request) {
+                Err(err) if attempt < self.throttle_retries => match err.retry_after() {
+                    Some(wait) if wait <= self.max_retry_after => {
+                        attempt += 1;
+                        thread::sleep(wait);
+                    }
+                    _ => return Err(err),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    fn write_payload_with_policy(
+        &self,
+        payload: CompressedPayload,
+        precision: Precision,
+        policy: &RetryPolicy,
+    ) -> Result<(), WriteError> {
+        let start = Instant::now();
+        let mut attempt = 0;
+        loop {
+            let request = self.builder.build_payload(payload.clone(), precision)?;
+            match self.send(request) {
+                Err(err) => {
+                    attempt += 1;
+                    if policy.drop_reason(attempt, start.elapsed(), &err).is_some() {
+                        return Err(err);
+                    }
+                    thread::sleep(policy.delay(attempt, &err));
+                }
+                Ok(()) => return Ok(()),
+            }
+        }
+    }
+
+    fn send(&self, request: http1::Request<Vec<u8>>) -> Result<(), WriteError> {
+        let request = self
+            .agent
+            .configure_request(request)
+            .http_status_as_error(false)
+            .build();
+        let mut response = self.agent.run(request).map_err(|err| match err {
+            ureq::Error::Io(err) => err.into(),
+            ureq::Error::StatusCode(status) => WriteError::from_response(status, "", None)
+                .unwrap_or_else(|| WriteError::Transport(format!("status {}", status))),
+            err => WriteError::Transport(err.to_string()),
+        })?;
+
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(http1::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let body = if (200..300).contains(&status) {
+            String::new()
+        } else {
+            response.body_mut().read_to_string().unwrap_or_default()
+        };
+        match WriteError::from_response(status, &body, retry_after.as_deref()) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Point, Timestamp};
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    /// Serve `responses` one per connection and return received requests.
+    fn stub(responses: Vec<&'static str>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = v.trim().parse().unwrap();
+                    }
+                    request += &line;
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                request += &String::from_utf8(body).unwrap();
+                requests.push(request);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn batch() -> Batch {
+        Batch::from(
+            Point::builder("cpu")
+                .unwrap()
+                .try_add_field(("a", 1i64))
+                .timestamp(Timestamp::Secs(1))
+                .build()
+                .unwrap(),
+        )
+    }
+
+    fn writer(url: &str) -> InfluxWriter {
+        InfluxWriter::new(WriteTarget::new(url, "org", "bucket").with_token("t"))
+    }
+
+    const NO_CONTENT: &str = "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n";
+
+    #[test]
+    fn write_success() {
+        let (url, server) = stub(vec![NO_CONTENT]);
+        writer(&url).write(&batch()).unwrap();
+
+        let requests = server.join().unwrap();
+        let request = &requests[0];
+        assert!(request.starts_with("POST /api/v2/write?org=org&bucket=bucket&precision=s "));
+        assert!(request.contains("authorization: Token t\r\n"));
+        assert!(request.ends_with("\r\n\r\ncpu a=1i 1"));
+    }
+
+    #[test]
+    fn map_errors() {
+        let (url, server) = stub(vec![
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 7\r\nConnection: close\r\n\r\ninvalid",
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 413 Payload Too Large\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 120\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let writer = writer(&url);
+        match writer.write(&batch()) {
+            Err(WriteError::BadRequest { message }) => assert_eq!(message, "invalid"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            writer.write(&batch()),
+            Err(WriteError::Unauthorized { .. })
+        ));
+        assert!(matches!(
+            writer.write(&batch()),
+            Err(WriteError::NotFound { .. })
+        ));
+        assert!(matches!(
+            writer.write(&batch()),
+            Err(WriteError::PayloadTooLarge { .. })
+        ));
+        match writer.write(&batch()) {
+            Err(err @ WriteError::ServiceUnavailable { .. }) => {
+                assert_eq!(err.retry_after(), Some(Duration::from_secs(120)))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            writer.write(&batch()),
+            Err(WriteError::UnexpectedStatus { status: 500, .. })
+        ));
+        assert_eq!(server.join().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn honor_retry_after() {
+        let too_many =
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (url, server) = stub(vec![too_many, NO_CONTENT, too_many, too_many]);
+        let writer = writer(&url);
+        writer.write(&batch()).unwrap();
+        assert!(matches!(
+            writer.write(&batch()),
+            Err(WriteError::TooManyRequests {
+                retry_after: Some(_)
+            })
+        ));
+        assert_eq!(server.join().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn custom_agent() {
+        let (url, server) = stub(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 7\r\nContent-Length: 4\r\nConnection: close\r\n\r\nslow",
+        ]);
+        // default agent config turns status codes into errors
+        let writer = InfluxWriter::with_agent(
+            WriteTarget::new(url, "org", "bucket"),
+            ureq::Agent::new_with_defaults(),
+        )
+        .throttle_retries(0);
+        match writer.write(&batch()) {
+            Err(err @ WriteError::TooManyRequests { .. }) => {
+                assert_eq!(err.retry_after(), Some(Duration::from_secs(7)))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn retry_policy() {
+        let unavailable =
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let bad = "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (url, server) = stub(vec![
+            unavailable,
+            unavailable,
+            NO_CONTENT,
+            bad,
+            unavailable,
+            unavailable,
+        ]);
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            jitter: Duration::from_millis(0),
+            max_attempts: 3,
+            ..Default::default()
+        };
+        let writer = writer(&url).retry_policy(policy);
+        writer.write(&batch()).unwrap();
+        // not retryable error is returned at once
+        assert!(matches!(
+            writer.write(&batch()),
+            Err(WriteError::BadRequest { .. })
+        ));
+        // stub has only 2 responses left, so third attempt would fail with a different error
+        let writer = writer.retry_policy(RetryPolicy {
+            max_attempts: 2,
+            ..policy
+        });
+        assert!(matches!(
+            writer.write(&batch()),
+            Err(WriteError::ServiceUnavailable { .. })
+        ));
+        assert_eq!(server.join().unwrap().len(), 6);
+    }
+}
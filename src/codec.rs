@@ -0,0 +1,231 @@
+//! `tokio-util` codec framing points as newline separated line protocol.
+
+use super::{
+    error::{CodecError, Error},
+    Point, Precision,
+};
+use bytes::{Buf, BufMut, BytesMut};
+use std::cmp;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Codec encoding [`Point`]s to lines and parsing lines back, for use with
+/// [`Framed`](tokio_util::codec::Framed), [`FramedRead`](tokio_util::codec::FramedRead) and
+/// [`FramedWrite`](tokio_util::codec::FramedWrite).
+///
+/// Empty lines and `#` comments are skipped. Lines longer than
+/// [`max_line_length`](LineProtocolCodec::max_line_length) are rejected without buffering them
+/// whole. Decoding errors consume the offending line, but `FramedRead` ends its stream after
+/// an error, so servers that must keep reading should create a new one from
+/// [`into_parts`](tokio_util::codec::FramedRead::into_parts).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineProtocolCodec {
+    max_line_length: usize,
+    precision: Precision,
+    // start of not yet searched part of buffer
+    next_index: usize,
+    // skipping rest of too long line
+    discarding: bool,
+}
+
+impl LineProtocolCodec {
+    /// Codec with 64KiB line limit and nanosecond timestamps.
+    pub fn new() -> Self {
+        Self {
+            max_line_length: 64 * 1024,
+            precision: Precision::Nanos,
+            next_index: 0,
+            discarding: false,
+        }
+    }
+
+    /// Maximum length of decoded line in bytes, without newline.
+    pub fn max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Precision of encoded and decoded timestamps.
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Maximum length of decoded line, see [`max_line_length`](LineProtocolCodec::max_line_length).
+    pub fn get_max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    /// Precision of timestamps, see [`precision`](LineProtocolCodec::precision).
+    pub fn get_precision(&self) -> Precision {
+        self.precision
+    }
+
+    // `None` for lines without point
+    fn parse(&self, line: &[u8]) -> Result<Option<Point>, CodecError> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line = std::str::from_utf8(line).map_err(|_| CodecError::InvalidLine {
+            line: String::from_utf8_lossy(line).into_owned(),
+            error: Error::Parse("invalid UTF-8".to_string()),
+        })?;
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(None);
+        }
+        Point::parse_line(trimmed, self.precision)
+            .map(Some)
+            .map_err(|error| CodecError::InvalidLine {
+                line: line.to_string(),
+                error,
+            })
+    }
+}
+
+impl Default for LineProtocolCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for LineProtocolCodec {
+    type Item = Point;
+    type Error = CodecError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Point>, CodecError> {
+        loop {
+            let read_to = cmp::min(self.max_line_length.saturating_add(1), buf.len());
+            let newline = buf[self.next_index..read_to]
+                .iter()
+                .position(|b| *b == b'\n')
+                .map(|i| self.next_index + i);
+            match (self.discarding, newline) {
+                (true, Some(i)) => {
+                    buf.advance(i + 1);
+                    self.discarding = false;
+                    self.next_index = 0;
+                }
+                (true, None) => {
+                    buf.advance(read_to);
+                    self.next_index = 0;
+                    if buf.is_empty() {
+                        return Ok(None);
+                    }
+                }
+                (false, Some(i)) => {
+                    self.next_index = 0;
+                    let line = buf.split_to(i + 1);
+                    if let Some(point) = self.parse(&line[..i])? {
+                        return Ok(Some(point));
+                    }
+                }
+                (false, None) if buf.len() > self.max_line_length => {
+                    self.discarding = true;
+                    return Err(CodecError::LineTooLong {
+                        max: self.max_line_length,
+                    });
+                }
+                (false, None) => {
+                    self.next_index = read_to;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Point>, CodecError> {
+        if let Some(point) = self.decode(buf)? {
+            return Ok(Some(point));
+        }
+        // last line without newline
+        self.next_index = 0;
+        if self.discarding {
+            self.discarding = false;
+            buf.clear();
+            return Ok(None);
+        }
+        let line = buf.split();
+        self.parse(&line)
+    }
+}
+
+impl Encoder<Point> for LineProtocolCodec {
+    type Error = CodecError;
+
+    fn encode(&mut self, point: Point, buf: &mut BytesMut) -> Result<(), CodecError> {
+        let mut line = point.to_text_with_precision(Some(self.precision));
+        line.push('\n');
+        buf.put_slice(line.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::point, Timestamp};
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    #[test]
+    fn decode_lines() {
+        let mut codec = LineProtocolCodec::new()
+            .max_line_length(12)
+            .precision(Precision::Secs);
+        let mut buf = BytesMut::from("# comment\r\n\nm v=1i 1\r\nm v=2i");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(point(1)));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b" 2\nm v=\"too long line\"");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(point(2)));
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(CodecError::LineTooLong { max: 12 })
+        ));
+        // rest of long line is dropped as it comes
+        buf.extend_from_slice(b" 5\nm v=x 3\nm v=4i 4");
+        match codec.decode(&mut buf) {
+            Err(CodecError::InvalidLine { line, .. }) => assert_eq!(line, "m v=x 3"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(point(4)));
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn framed() {
+        let mut out = vec![];
+        let mut writer = FramedWrite::new(&mut out, LineProtocolCodec::new());
+        for i in 0..3 {
+            writer.send(point(i)).await.unwrap();
+        }
+        drop(writer);
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            "m v=0i 0\nm v=1i 1000000000\nm v=2i 2000000000\n"
+        );
+
+        let reader = FramedRead::new(&out[..], LineProtocolCodec::new());
+        let points = reader
+            .map(|p| p.unwrap().timestamp())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            points,
+            vec![
+                Timestamp::Nanos(0),
+                Timestamp::Nanos(1_000_000_000),
+                Timestamp::Nanos(2_000_000_000)
+            ]
+        );
+
+        // timestamps are read as written, precision is not detected
+        let reader = FramedRead::new(
+            &out[..],
+            LineProtocolCodec::new().precision(Precision::Secs),
+        );
+        let points = reader
+            .map(|p| p.unwrap().timestamp())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(points[1], Timestamp::Secs(1_000_000_000));
+    }
+}
@@ -0,0 +1,574 @@
+//! Columnar (struct-of-arrays) representation of [`Batch`].
+//!
+//! Measurements, tag keys and tag values are dictionary encoded, so each distinct string is stored
+//! (and escaped) once. Fields are kept in sparse typed columns per measurement, holding values of
+//! points that have the field only, and timestamps in an `i64` column.
+
+use super::{
+    escape, number, Batch, Field, FieldKey, FieldType, FieldValue, Measurement, Point, Precision,
+    Tag, TagKey, TagSet, TagValue, Timestamp,
+};
+use ordered_float::NotNan;
+use std::{collections::HashMap, fmt::Write, hash::Hash, iter::FromIterator};
+
+/// Distinct values with their escaped text, referenced by id.
+#[derive(Debug, Clone)]
+struct Dictionary<T> {
+    values: Vec<T>,
+    escaped: Vec<String>,
+    ids: HashMap<T, u32>,
+    escape: fn(&str) -> String,
+}
+
+impl<T> Dictionary<T>
+where
+    T: Hash + Eq + Clone + AsRef<str>,
+{
+    fn new(escape: fn(&str) -> String) -> Self {
+        Self {
+            values: vec![],
+            escaped: vec![],
+            ids: HashMap::new(),
+            escape,
+        }
+    }
+
+    fn insert(&mut self, value: &T) -> u32 {
+        if let Some(id) = self.ids.get(value) {
+            return *id;
+        }
+        let id = self.values.len() as u32;
+        self.escaped.push((self.escape)(value.as_ref()));
+        self.values.push(value.clone());
+        self.ids.insert(value.clone(), id);
+        id
+    }
+
+    fn get(&self, id: u32) -> &T {
+        &self.values[id as usize]
+    }
+
+    fn escaped(&self, id: u32) -> &str {
+        &self.escaped[id as usize]
+    }
+}
+
+/// Values of a single field column. Only points that have the field store a value.
+#[derive(Debug, Clone)]
+enum Values {
+    String(Vec<String>),
+    UInteger(Vec<u64>),
+    Integer(Vec<i64>),
+    Float(Vec<NotNan<f64>>),
+    Boolean(Vec<bool>),
+}
+
+impl Values {
+    fn new(field_type: FieldType) -> Self {
+        match field_type {
+            FieldType::String => Values::String(vec![]),
+            FieldType::UInteger => Values::UInteger(vec![]),
+            FieldType::Integer => Values::Integer(vec![]),
+            FieldType::Float => Values::Float(vec![]),
+            FieldType::Boolean => Values::Boolean(vec![]),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Values::String(v) => v.len(),
+            Values::UInteger(v) => v.len(),
+            Values::Integer(v) => v.len(),
+            Values::Float(v) => v.len(),
+            Values::Boolean(v) => v.len(),
+        }
+    }
+
+    /// Append value, returns its position in column.
+    fn push(&mut self, value: FieldValue) -> u32 {
+        let index = self.len() as u32;
+        match (self, value) {
+            (Values::String(v), FieldValue::String(s)) => v.push(s),
+            (Values::UInteger(v), FieldValue::UInteger(i)) => v.push(i),
+            (Values::Integer(v), FieldValue::Integer(i)) => v.push(i),
+            (Values::Float(v), FieldValue::Float(f)) => v.push(f),
+            (Values::Boolean(v), FieldValue::Boolean(b)) => v.push(b),
+            _ => unreachable!("columns are selected by field type"),
+        }
+        index
+    }
+
+    fn get(&self, index: u32) -> FieldValue {
+        let index = index as usize;
+        match self {
+            Values::String(v) => FieldValue::String(v[index].clone()),
+            Values::UInteger(v) => FieldValue::UInteger(v[index]),
+            Values::Integer(v) => FieldValue::Integer(v[index]),
+            Values::Float(v) => FieldValue::Float(v[index]),
+            Values::Boolean(v) => FieldValue::Boolean(v[index]),
+        }
+    }
+
+    // write value in the same form as `FieldValue::to_text`
+    fn write_text(&self, index: u32, out: &mut String) {
+        let index = index as usize;
+        match self {
+            Values::String(v) => {
+                out.push('"');
+                *out += &escape::field_value(&v[index]);
+                out.push('"');
+            }
+            Values::UInteger(v) => {
+                number::push_integer(out, v[index]);
+                out.push('u');
+            }
+            Values::Integer(v) => {
+                number::push_integer(out, v[index]);
+                out.push('i');
+            }
+            Values::Float(v) => number::push_float(out, v[index].into_inner()),
+            Values::Boolean(v) => out.push_str(if v[index] { "true" } else { "false" }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FieldColumn {
+    key: FieldKey,
+    escaped_key: String,
+    values: Values,
+}
+
+/// Field columns of a single measurement.
+#[derive(Debug, Clone, Default)]
+struct FieldTable {
+    columns: Vec<FieldColumn>,
+    ids: HashMap<(FieldKey, FieldType), u32>,
+}
+
+impl FieldTable {
+    /// Append value to column of its key and type, returns column id and position in column.
+    fn push(&mut self, field: &Field) -> (u32, u32) {
+        let column_key = (field.key().clone(), field.value().field_type());
+        let id = match self.ids.get(&column_key) {
+            Some(id) => *id,
+            None => {
+                let id = self.columns.len() as u32;
+                self.columns.push(FieldColumn {
+                    key: column_key.0.clone(),
+                    escaped_key: escape::field_key(&column_key.0),
+                    values: Values::new(column_key.1),
+                });
+                self.ids.insert(column_key, id);
+                id
+            }
+        };
+        (
+            id,
+            self.columns[id as usize].values.push(field.value().clone()),
+        )
+    }
+}
+
+/// A collection of data points stored column by column.
+///
+/// Use it instead of [`Batch`] when a lot of points share the same measurements and tags.
+/// Points are rebuilt and encoded with fields in the order they were added, duplicated keys
+/// included, so output is the same as of [`Batch`].
+#[derive(Debug, Clone)]
+pub struct ColumnarBatch {
+    measurements: Dictionary<Measurement>,
+    tag_keys: Dictionary<TagKey>,
+    tag_values: Dictionary<TagValue>,
+
+    measurement: Vec<u32>,
+    // tags of point `i` are `tags[tag_offsets[i]..tag_offsets[i + 1]]`
+    tag_offsets: Vec<usize>,
+    tags: Vec<(u32, u32)>,
+    // field table is selected by measurement id, fields of point `i` are
+    // `fields[field_offsets[i]..field_offsets[i + 1]]` as (column id, position in column)
+    field_tables: Vec<FieldTable>,
+    field_offsets: Vec<usize>,
+    fields: Vec<(u32, u32)>,
+    timestamps: Vec<i64>,
+    precisions: Vec<Option<Precision>>,
+
+    default_tags: TagSet,
+}
+
+impl Default for ColumnarBatch {
+    fn default() -> Self {
+        Self {
+            measurements: Dictionary::new(escape::measurement),
+            tag_keys: Dictionary::new(escape::tag_key),
+            tag_values: Dictionary::new(escape::tag_value),
+            measurement: vec![],
+            tag_offsets: vec![0],
+            tags: vec![],
+            field_tables: vec![],
+            field_offsets: vec![0],
+            fields: vec![],
+            timestamps: vec![],
+            precisions: vec![],
+            default_tags: vec![],
+        }
+    }
+}
+
+impl ColumnarBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.measurement.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.measurement.is_empty()
+    }
+
+    /// Tags added to every point. See [`Batch::with_default_tags`].
+    pub fn default_tags(&self) -> &[Tag] {
+        &self.default_tags
+    }
+
+    pub fn push_point(&mut self, p: impl Into<Point>) {
+        let point = p.into();
+        let timestamp = point.timestamp();
+        let (measurement, tag_set, field_set) = point.into_parts();
+
+        let m_id = self.measurements.insert(&measurement);
+        if m_id as usize == self.field_tables.len() {
+            self.field_tables.push(FieldTable::default());
+        }
+        self.measurement.push(m_id);
+
+        for tag in &tag_set {
+            let key = self.tag_keys.insert(tag.key());
+            let value = self.tag_values.insert(tag.value());
+            self.tags.push((key, value));
+        }
+        self.tag_offsets.push(self.tags.len());
+
+        let table = &mut self.field_tables[m_id as usize];
+        for field in &field_set {
+            self.fields.push(table.push(field));
+        }
+        self.field_offsets.push(self.fields.len());
+
+        self.timestamps.push(timestamp.value().unwrap_or_default());
+        self.precisions.push(timestamp.precision());
+    }
+
+    /// Rebuild point at position `index`.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn point(&self, index: usize) -> Point {
+        let m_id = self.measurement[index];
+        let tag_set = self
+            .point_tags(index)
+            .iter()
+            .map(|(k, v)| {
+                Tag::from_parts(
+                    self.tag_keys.get(*k).clone(),
+                    self.tag_values.get(*v).clone(),
+                )
+            })
+            .collect();
+
+        let table = &self.field_tables[m_id as usize];
+        let field_set = self
+            .point_fields(index)
+            .iter()
+            .map(|(column, i)| {
+                let column = &table.columns[*column as usize];
+                Field::from_parts(column.key.clone(), column.values.get(*i))
+            })
+            .collect();
+
+        Point::from_parts(
+            self.measurements.get(m_id).clone(),
+            tag_set,
+            field_set,
+            Timestamp::from_precision(self.precisions[index], self.timestamps[index]),
+        )
+    }
+
+    /// Iterate over rebuilt points.
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..self.len()).map(move |i| self.point(i))
+    }
+
+    /// This will build batch in InfluxDB line protocol format directly from columns.
+    ///
+    /// Output is the same as [`Batch::to_line_protocol_lossy`] of batch converted from this one.
+    pub fn to_line_protocol_lossy(&self, precision: Option<Precision>) -> String {
+        let mut out = String::new();
+        let default_tags = self
+            .default_tags
+            .iter()
+            .map(|t| {
+                let escaped = (escape::tag_key(t.key()), escape::tag_value(t.value()));
+                (t.key(), t.value(), escaped)
+            })
+            .collect::<Vec<_>>();
+
+        for index in 0..self.len() {
+            if index > 0 {
+                out.push('\n');
+            }
+            self.write_line(index, precision, &default_tags, &mut out)
+                .expect("writing to String never fails");
+        }
+        out
+    }
+
+    fn point_tags(&self, index: usize) -> &[(u32, u32)] {
+        &self.tags[self.tag_offsets[index]..self.tag_offsets[index + 1]]
+    }
+
+    fn point_fields(&self, index: usize) -> &[(u32, u32)] {
+        &self.fields[self.field_offsets[index]..self.field_offsets[index + 1]]
+    }
+
+    fn write_line(
+        &self,
+        index: usize,
+        precision: Option<Precision>,
+        default_tags: &[(&TagKey, &TagValue, (String, String))],
+        out: &mut String,
+    ) -> std::fmt::Result {
+        let m_id = self.measurement[index];
+        out.push_str(self.measurements.escaped(m_id));
+
+        let tags = self.point_tags(index);
+        if default_tags.is_empty() {
+            for (k, v) in tags {
+                write!(
+                    out,
+                    ",{}={}",
+                    self.tag_keys.escaped(*k),
+                    self.tag_values.escaped(*v)
+                )?;
+            }
+        } else {
+            let mut merged = tags
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        self.tag_keys.get(*k),
+                        self.tag_values.get(*v),
+                        self.tag_keys.escaped(*k),
+                        self.tag_values.escaped(*v),
+                    )
+                })
+                .collect::<Vec<_>>();
+            for (key, value, (escaped_key, escaped_value)) in default_tags {
+                if merged.iter().all(|t| t.0 != *key) {
+                    merged.push((key, value, escaped_key, escaped_value));
+                }
+            }
+            merged.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+            for (_, _, k, v) in merged {
+                write!(out, ",{}={}", k, v)?;
+            }
+        }
+
+        let table = &self.field_tables[m_id as usize];
+        let mut separator = ' ';
+        for (column, i) in self.point_fields(index) {
+            let column = &table.columns[*column as usize];
+            out.push(separator);
+            separator = ',';
+            out.push_str(&column.escaped_key);
+            out.push('=');
+            column.values.write_text(*i, out);
+        }
+
+        let ts = Timestamp::from_precision(self.precisions[index], self.timestamps[index]);
+        let ts = precision
+            .map(|p| ts.timestamp_precision_lossy(p))
+            .unwrap_or(ts);
+        if let Some(v) = ts.value() {
+            out.push(' ');
+            number::push_integer(out, v);
+        }
+        Ok(())
+    }
+}
+
+impl From<Batch> for ColumnarBatch {
+    fn from(batch: Batch) -> Self {
+        let mut columnar = Self {
+            default_tags: batch.default_tags().to_vec(),
+            ..Default::default()
+        };
+        for point in batch {
+            columnar.push_point(point);
+        }
+        columnar
+    }
+}
+
+impl From<ColumnarBatch> for Batch {
+    fn from(columnar: ColumnarBatch) -> Self {
+        let mut batch = Batch::with_capacity(columnar.len());
+        batch.extend(columnar.points());
+        batch.with_default_tags(columnar.default_tags)
+    }
+}
+
+impl<P> FromIterator<P> for ColumnarBatch
+where
+    P: Into<Point>,
+{
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        let mut columnar = Self::default();
+        for point in iter {
+            columnar.push_point(point);
+        }
+        columnar
+    }
+}
+
+impl<P> Extend<P> for ColumnarBatch
+where
+    P: Into<Point>,
+{
+    fn extend<I: IntoIterator<Item = P>>(&mut self, iter: I) {
+        for point in iter {
+            self.push_point(point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn batch() -> Batch {
+        let cpu = |host: &str, ts: Timestamp| {
+            Point::builder("cpu load")
+                .unwrap()
+                .try_add_tags(vec![("host", host), ("core", "0")])
+                .try_add_field(("user", 0.5f64))
+                .try_add_field(("msg", r#"a "quoted", \ message"#))
+                .timestamp(ts)
+                .build()
+                .unwrap()
+        };
+        let mem = Point::builder("mem")
+            .unwrap()
+            .try_add_field(("used", 10u64))
+            .try_add_field(("ok", true))
+            .build()
+            .unwrap();
+        // type conflict with first `cpu load` point
+        let cpu_int = Point::builder("cpu load")
+            .unwrap()
+            .try_add_field(("user", 1i64))
+            .timestamp(Timestamp::Milli(-5))
+            .build()
+            .unwrap();
+
+        Batch::from(vec![
+            cpu("a b", Timestamp::Secs(1)),
+            mem,
+            cpu("a=b", Timestamp::Nanos(7)),
+            cpu_int,
+        ])
+    }
+
+    #[test]
+    fn duplicated_field_key() {
+        let p = Point::builder("m")
+            .unwrap()
+            .try_add_field(("v", 1i64))
+            .try_add_field(("w", true))
+            .try_add_field(("v", 2i64))
+            .try_add_field(("v", 0.5f64))
+            .build()
+            .unwrap();
+        let columnar = ColumnarBatch::from(Batch::from(p.clone()));
+        assert_eq!(
+            columnar.to_line_protocol_lossy(None),
+            "m v=1i,w=true,v=2i,v=0.5"
+        );
+        assert_eq!(columnar.point(0), p);
+    }
+
+    #[test]
+    fn keep_field_order() {
+        let point = |fields: &[(&str, i64)]| {
+            Point::builder("m")
+                .unwrap()
+                .try_add_fields(fields.to_vec())
+                .build()
+                .unwrap()
+        };
+        let b = Batch::from(vec![
+            point(&[("a", 1), ("b", 2)]),
+            point(&[("b", 3), ("a", 4)]),
+            point(&[("c", 5)]),
+            point(&[("b", 6), ("c", 7), ("a", 8)]),
+        ]);
+        let columnar = ColumnarBatch::from(b.clone());
+        let lines = columnar.to_line_protocol_lossy(None);
+        assert_eq!(lines, b.to_line_protocol_lossy(None));
+        assert_eq!(lines, "m a=1i,b=2i\nm b=3i,a=4i\nm c=5i\nm b=6i,c=7i,a=8i");
+        assert_eq!(
+            columnar.points().collect::<Vec<_>>(),
+            b.iter().cloned().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn encode_same_as_batch() {
+        let b = batch();
+        let columnar = ColumnarBatch::from(b.clone());
+        assert_eq!(columnar.len(), 4);
+        for precision in [None, Some(Precision::Secs), Some(Precision::Nanos)] {
+            assert_eq!(
+                columnar.to_line_protocol_lossy(precision),
+                b.to_line_protocol_lossy(precision)
+            );
+        }
+
+        let b = b.with_default_tags(vec![
+            Tag::try_from(("region", "eu")).unwrap(),
+            Tag::try_from(("host", "default")).unwrap(),
+        ]);
+        let columnar = ColumnarBatch::from(b.clone());
+        assert_eq!(
+            columnar.to_line_protocol_lossy(None),
+            b.to_line_protocol_lossy(None)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let b = batch();
+        let points = b.iter().cloned().collect::<Vec<_>>();
+        let back = Batch::from(ColumnarBatch::from(b));
+        assert_eq!(back.into_iter().collect::<Vec<_>>(), points);
+    }
+
+    #[test]
+    fn dictionary_encoding() {
+        let columnar = batch().into_iter().collect::<ColumnarBatch>();
+        assert_eq!(columnar.measurements.values.len(), 2);
+        assert_eq!(columnar.tag_keys.values.len(), 2);
+        assert_eq!(columnar.tag_values.values.len(), 3);
+        // `user` is float and integer column
+        let columns = &columnar.field_tables[0].columns;
+        assert_eq!(columns.len(), 3);
+        // only points with the field store a value
+        assert_eq!(
+            columns.iter().map(|c| c.values.len()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+    }
+}
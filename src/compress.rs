@@ -0,0 +1,277 @@
+//! Compressed line protocol payloads.
+//!
+//! Each algorithm is behind its own cargo feature: `gzip`, `zstd` and `snappy`.
+
+use std::io::{self, Write};
+
+/// Compression algorithm applied to line protocol payload.
+///
+/// Variants depend on enabled features, so matches outside this crate need a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Payload is not compressed.
+    #[default]
+    None,
+    /// Gzip with `level` from 0 (no compression) to 9 (best). Supported by InfluxDB HTTP API.
+    #[cfg(feature = "gzip")]
+    Gzip { level: u32 },
+    /// Zstandard with `level` from 1 to 22 (0 means zstd default level).
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+    /// Snappy in [framing format](https://github.com/google/snappy/blob/master/framing_format.txt).
+    #[cfg(feature = "snappy")]
+    Snappy,
+}
+
+impl Compression {
+    /// Value of `Content-Encoding` header for payload compressed with this algorithm.
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip { .. } => Some("gzip"),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => Some("zstd"),
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => Some("snappy"),
+        }
+    }
+
+    /// Wrap `writer` into streaming compressor.
+    pub fn writer<W: Write>(self, writer: W) -> io::Result<CompressWriter<W>> {
+        let inner = match self {
+            Compression::None => Inner::None(writer),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip { level } => Inner::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::new(level),
+            )),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => {
+                Inner::Zstd(zstd::stream::write::Encoder::new(writer, level)?)
+            }
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => Inner::Snappy(Box::new(snap::write::FrameEncoder::new(writer))),
+        };
+        Ok(CompressWriter {
+            inner,
+            compression: self,
+        })
+    }
+
+    /// Compress whole `data` at once.
+    pub fn compress(self, data: &[u8]) -> io::Result<CompressedPayload> {
+        let mut writer = self.writer(Vec::new())?;
+        writer.write_all(data)?;
+        writer.finish_payload()
+    }
+}
+
+/// Decompress `data` sent with `Content-Encoding: content_encoding`. Only algorithms of enabled
+/// features are supported.
+#[cfg(feature = "testing")]
+pub(crate) fn decompress(content_encoding: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    #[cfg(any(feature = "gzip", feature = "snappy"))]
+    use std::io::Read;
+    let mut out = vec![];
+    match content_encoding.trim() {
+        "" | "identity" => out.extend_from_slice(data),
+        #[cfg(feature = "gzip")]
+        "gzip" => {
+            flate2::read::GzDecoder::new(data).read_to_end(&mut out)?;
+        }
+        #[cfg(feature = "zstd")]
+        "zstd" => out = zstd::decode_all(data)?,
+        #[cfg(feature = "snappy")]
+        "snappy" => {
+            snap::read::FrameDecoder::new(data).read_to_end(&mut out)?;
+        }
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported content encoding `{}`", other),
+            ))
+        }
+    }
+    Ok(out)
+}
+
+enum Inner<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    #[cfg(feature = "snappy")]
+    Snappy(Box<snap::write::FrameEncoder<W>>),
+}
+
+/// Streaming compressor created by [`Compression::writer`].
+///
+/// Call [`finish`](CompressWriter::finish) to write compression trailer and get the inner writer
+/// back. Dropping it without `finish` may leave output truncated.
+pub struct CompressWriter<W: Write> {
+    inner: Inner<W>,
+    compression: Compression,
+}
+
+impl<W: Write> std::fmt::Debug for CompressWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompressWriter")
+            .field("compression", &self.compression)
+            .finish()
+    }
+}
+
+impl<W: Write> CompressWriter<W> {
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Finish compressed stream and return inner writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self.inner {
+            Inner::None(mut w) => {
+                w.flush()?;
+                Ok(w)
+            }
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(w) => w.finish(),
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(w) => w.finish(),
+            #[cfg(feature = "snappy")]
+            Inner::Snappy(w) => w.into_inner().map_err(|e| e.into_error()),
+        }
+    }
+}
+
+impl CompressWriter<Vec<u8>> {
+    /// Finish compressed stream and return it as labeled payload.
+    pub fn finish_payload(self) -> io::Result<CompressedPayload> {
+        let compression = self.compression;
+        Ok(CompressedPayload {
+            data: self.finish()?,
+            compression,
+        })
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::None(w) => w.write(buf),
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(w) => w.write(buf),
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(w) => w.write(buf),
+            #[cfg(feature = "snappy")]
+            Inner::Snappy(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Inner::None(w) => w.flush(),
+            #[cfg(feature = "gzip")]
+            Inner::Gzip(w) => w.flush(),
+            #[cfg(feature = "zstd")]
+            Inner::Zstd(w) => w.flush(),
+            #[cfg(feature = "snappy")]
+            Inner::Snappy(w) => w.flush(),
+        }
+    }
+}
+
+/// Line protocol payload labeled with [`Compression`] used to create it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedPayload {
+    data: Vec<u8>,
+    compression: Compression,
+}
+
+impl CompressedPayload {
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Value of `Content-Encoding` header that transport should set.
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl AsRef<[u8]> for CompressedPayload {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(any(feature = "gzip", feature = "snappy"))]
+    use std::io::Read;
+
+    const PAYLOAD: &str = "cpu,host=a value=1i 1\ncpu,host=a value=2i 2\ncpu,host=a value=3i 3";
+
+    #[test]
+    fn none() {
+        let payload = Compression::None.compress(PAYLOAD.as_bytes()).unwrap();
+        assert_eq!(payload.as_bytes(), PAYLOAD.as_bytes());
+        assert_eq!(payload.content_encoding(), None);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip() {
+        let payload = Compression::Gzip { level: 6 }
+            .compress(PAYLOAD.as_bytes())
+            .unwrap();
+        assert_eq!(payload.content_encoding(), Some("gzip"));
+        let mut out = String::new();
+        flate2::read::GzDecoder::new(payload.as_bytes())
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, PAYLOAD);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        let payload = Compression::Zstd { level: 3 }
+            .compress(PAYLOAD.as_bytes())
+            .unwrap();
+        assert_eq!(payload.content_encoding(), Some("zstd"));
+        let out = zstd::stream::decode_all(payload.as_bytes()).unwrap();
+        assert_eq!(out, PAYLOAD.as_bytes());
+    }
+
+    #[cfg(feature = "snappy")]
+    #[test]
+    fn snappy() {
+        let payload = Compression::Snappy.compress(PAYLOAD.as_bytes()).unwrap();
+        assert_eq!(payload.content_encoding(), Some("snappy"));
+        let mut out = String::new();
+        snap::read::FrameDecoder::new(payload.as_bytes())
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, PAYLOAD);
+    }
+}
@@ -0,0 +1,338 @@
+//! Storage for points that can't be written, so they can be inspected and reprocessed.
+
+use super::{
+    error::{LineError, WriteError},
+    sync, Batch, Point, Precision, Tag,
+};
+use std::{
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+#[cfg(feature = "serde_json")]
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Where a point was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Origin {
+    /// Point could not be built.
+    Validation,
+    /// Line protocol could not be parsed.
+    Parse,
+    /// Server refused the write.
+    Server,
+}
+
+impl Origin {
+    fn as_str(self) -> &'static str {
+        match self {
+            Origin::Validation => "validation",
+            Origin::Parse => "parse",
+            Origin::Server => "server",
+        }
+    }
+
+    #[cfg(feature = "serde_json")]
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "validation" => Origin::Validation,
+            "parse" => Origin::Parse,
+            "server" => Origin::Server,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Rejected line with reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetterEntry {
+    /// Line protocol, or as much of it as was known when point was rejected.
+    pub line: String,
+    pub error: String,
+    pub failed_at: SystemTime,
+    pub origin: Origin,
+}
+
+impl DeadLetterEntry {
+    /// Entry failed now.
+    pub fn new(line: impl Into<String>, error: impl fmt::Display, origin: Origin) -> Self {
+        Self {
+            line: line.into(),
+            error: error.to_string(),
+            failed_at: SystemTime::now(),
+            origin,
+        }
+    }
+
+    /// Entry for `point` refused by server, with `default_tags` of its batch added.
+    pub fn rejected(
+        point: &Point,
+        default_tags: &[Tag],
+        error: impl fmt::Display,
+        precision: Option<Precision>,
+    ) -> Self {
+        Self::new(
+            point.to_text_with_default_tags(precision, default_tags),
+            error,
+            Origin::Server,
+        )
+    }
+}
+
+/// Destination of rejected points.
+pub trait DeadLetter {
+    fn send(&mut self, entry: DeadLetterEntry) -> io::Result<()>;
+
+    /// Send points split off by [`Batch::split_rejected`], with `default_tags` of the batch
+    /// added to each line.
+    fn send_rejected(
+        &mut self,
+        rejected: Vec<(Point, LineError)>,
+        default_tags: &[Tag],
+        precision: Option<Precision>,
+    ) -> io::Result<()> {
+        for (point, error) in rejected {
+            self.send(DeadLetterEntry::rejected(
+                &point,
+                default_tags,
+                error.reason,
+                precision,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Send every point of `batch` whose write failed with `error`.
+    fn send_batch(
+        &mut self,
+        batch: &Batch,
+        error: &WriteError,
+        precision: Option<Precision>,
+    ) -> io::Result<()> {
+        for point in batch {
+            let line = point.to_text_with_default_tags(precision, batch.default_tags());
+            self.send(DeadLetterEntry::new(line, error, Origin::Server))?;
+        }
+        Ok(())
+    }
+}
+
+impl<D: DeadLetter + ?Sized> DeadLetter for &mut D {
+    fn send(&mut self, entry: DeadLetterEntry) -> io::Result<()> {
+        (**self).send(entry)
+    }
+}
+
+/// Entries kept in memory. Clones share the same entries.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDeadLetter {
+    entries: Arc<Mutex<Vec<DeadLetterEntry>>>,
+}
+
+impl MemoryDeadLetter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<DeadLetterEntry> {
+        self.lock().clone()
+    }
+
+    /// Take all entries, for example to reprocess them.
+    pub fn take(&self) -> Vec<DeadLetterEntry> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<DeadLetterEntry>> {
+        sync::lock(&self.entries)
+    }
+}
+
+impl DeadLetter for MemoryDeadLetter {
+    fn send(&mut self, entry: DeadLetterEntry) -> io::Result<()> {
+        self.lock().push(entry);
+        Ok(())
+    }
+}
+
+/// Entries appended to a file as JSON lines.
+///
+/// Each line is `{"failed_at": <unix nanos>, "origin": .., "error": .., "line": ..}`. Requires
+/// `serde_json` feature, which `http` and `testing` enable.
+#[cfg(feature = "serde_json")]
+#[derive(Debug)]
+pub struct FileDeadLetter {
+    path: PathBuf,
+    file: File,
+}
+
+#[cfg(feature = "serde_json")]
+impl FileDeadLetter {
+    /// Open `path` for appending, creating it if needed.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read all entries of file at `path`.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<DeadLetterEntry>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut entries = vec![];
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(parse_entry(&line).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid dead letter entry")
+            })?);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl DeadLetter for FileDeadLetter {
+    fn send(&mut self, entry: DeadLetterEntry) -> io::Result<()> {
+        let failed_at = entry
+            .failed_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let json = serde_json::json!({
+            "failed_at": failed_at,
+            "origin": entry.origin.as_str(),
+            "error": entry.error,
+            "line": entry.line,
+        });
+        let mut text = json.to_string();
+        text.push('\n');
+        // one write per entry, so concurrent appends don't interleave
+        self.file.write_all(text.as_bytes())
+    }
+}
+
+#[cfg(feature = "serde_json")]
+fn parse_entry(line: &str) -> Option<DeadLetterEntry> {
+    let json = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    Some(DeadLetterEntry {
+        line: json.get("line")?.as_str()?.to_string(),
+        error: json.get("error")?.as_str()?.to_string(),
+        failed_at: UNIX_EPOCH + Duration::from_nanos(json.get("failed_at")?.as_u64()?),
+        origin: Origin::parse(json.get("origin")?.as_str()?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::Error, test_util::point, Tag};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn memory_dead_letter() {
+        let dead_letter = MemoryDeadLetter::new();
+        let mut sink = dead_letter.clone();
+
+        let built = Point::builder("m")
+            .unwrap()
+            .try_add_field(("v", 1i64))
+            .try_add_field(("_bad", 2i64))
+            .build_or_dead_letter(&mut sink)
+            .unwrap();
+        assert!(built.is_none());
+        let valid = Point::builder("m")
+            .unwrap()
+            .try_add_field(("v", 1i64))
+            .build_or_dead_letter(&mut sink)
+            .unwrap();
+        assert!(valid.is_some());
+        let empty = Point::builder("m")
+            .unwrap()
+            .build_or_dead_letter(&mut sink)
+            .unwrap();
+        assert!(empty.is_none());
+
+        let batch = Batch::from(vec![point(1), point(2)])
+            .with_default_tags(vec![Tag::try_from(("host", "a")).unwrap()]);
+        let error = WriteError::NotFound {
+            message: "bucket not found".into(),
+        };
+        sink.send_batch(&batch, &error, None).unwrap();
+
+        let entries = dead_letter.take();
+        assert!(dead_letter.is_empty());
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].origin, Origin::Validation);
+        assert_eq!(entries[0].line, "m v=1i");
+        assert_eq!(entries[0].error, Error::StartWithForbieden_.to_string());
+        assert_eq!(entries[1].origin, Origin::Validation);
+        assert_eq!(entries[1].line, "m");
+        assert_eq!(entries[1].error, Error::NoField.to_string());
+        assert_eq!(entries[3].origin, Origin::Server);
+        assert_eq!(entries[3].line, "m,host=a v=2i 2");
+        assert_eq!(entries[3].error, "Not found: bucket not found");
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn file_dead_letter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead.jsonl");
+        let mut sink = FileDeadLetter::open(&path).unwrap();
+
+        let batch = Batch::from(vec![point(1), point(2)])
+            .with_default_tags(vec![Tag::try_from(("host", "a")).unwrap()]);
+        let errors = [LineError {
+            line: Some(2),
+            original_line: None,
+            reason: "field type \"conflict\"\nnew line".to_string(),
+        }];
+        let (accepted, rejected) = batch.clone().split_rejected(&errors, Some(Precision::Secs));
+        sink.send_rejected(rejected, accepted.default_tags(), Some(Precision::Secs))
+            .unwrap();
+        sink.send(DeadLetterEntry::new(
+            "not line protocol",
+            "bad",
+            Origin::Parse,
+        ))
+        .unwrap();
+        drop(sink);
+
+        let mut sink = FileDeadLetter::open(&path).unwrap();
+        sink.send_batch(&batch, &WriteError::Closed, None).unwrap();
+
+        let entries = FileDeadLetter::read(&path).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].line, "m,host=a v=2i 2");
+        assert_eq!(entries[0].error, "field type \"conflict\"\nnew line");
+        assert_eq!(entries[1].origin, Origin::Parse);
+        assert_eq!(entries[3].error, "Writer is closed");
+        assert!(entries[0].failed_at <= SystemTime::now());
+        assert!(entries[0].failed_at > UNIX_EPOCH);
+    }
+}
@@ -0,0 +1,589 @@
+//! Write-ahead queue of encoded batches stored in segment files.
+
+use super::{Batch, Precision};
+use std::{
+    cmp,
+    collections::VecDeque,
+    convert::TryFrom,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const SEGMENT_EXTENSION: &str = "seg";
+const CURSOR_FILE: &str = "cursor";
+// payload length, checksum, precision
+const HEADER_LEN: u64 = 9;
+
+/// Size limits of [`DiskQueue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskQueueConfig {
+    /// New segment file is started when current one has at least this many bytes.
+    pub max_segment_bytes: u64,
+    /// Oldest segments are removed when queue would take more bytes on disk.
+    pub max_disk_bytes: u64,
+    /// Call `fsync` after every pushed batch.
+    pub sync: bool,
+}
+
+impl Default for DiskQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_segment_bytes: 8 * 1024 * 1024,
+            max_disk_bytes: 1024 * 1024 * 1024,
+            sync: false,
+        }
+    }
+}
+
+/// Encoded batch read back from [`DiskQueue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedPayload {
+    /// Line protocol.
+    pub payload: String,
+    /// Precision of all timestamps in `payload`.
+    pub precision: Precision,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Debug)]
+struct Segment {
+    id: u64,
+    file_len: u64,
+    records: VecDeque<Record>,
+}
+
+/// Batches that could not be written yet, kept in a directory so they survive process restart.
+///
+/// Every batch is encoded once and appended as a checksummed record to the newest segment file.
+/// Records are read back in the same order. A record is removed only after
+/// [`pop_front`](DiskQueue::pop_front), so a batch whose write failed is replayed again.
+///
+/// Damaged records (for example not fully written before crash) and everything after them in the
+/// same segment are skipped when queue is opened.
+#[derive(Debug)]
+pub struct DiskQueue {
+    dir: PathBuf,
+    config: DiskQueueConfig,
+    segments: VecDeque<Segment>,
+    // append handle of the last segment
+    writer: Option<File>,
+    next_id: u64,
+    disk_bytes: u64,
+    skipped: usize,
+}
+
+impl DiskQueue {
+    /// Open queue in `dir`, creating the directory if needed, and load records left by previous
+    /// process.
+    pub fn open(dir: impl Into<PathBuf>, config: DiskQueueConfig) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let cursor = read_cursor(&dir)?;
+
+        let mut ids = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+            {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+
+        let mut queue = Self {
+            dir,
+            config,
+            segments: VecDeque::new(),
+            writer: None,
+            // drained queue leaves only cursor, new segments must not be below it
+            next_id: cmp::max(
+                ids.last().map_or(0, |id| id + 1),
+                cursor.map_or(0, |(id, _)| id),
+            ),
+            disk_bytes: 0,
+            skipped: 0,
+        };
+        for id in ids {
+            let path = queue.segment_path(id);
+            let (first_unread, read) = match cursor {
+                Some((cursor_id, _)) if id < cursor_id => {
+                    fs::remove_file(&path)?;
+                    continue;
+                }
+                Some((cursor_id, offset)) if id == cursor_id => (offset, true),
+                _ => (0, false),
+            };
+            let (records, file_len, damaged) = scan_segment(&path)?;
+            if damaged {
+                queue.skipped += 1;
+            }
+            let records = records
+                .into_iter()
+                .filter(|r| !read || r.offset >= first_unread)
+                .collect::<VecDeque<_>>();
+            if records.is_empty() {
+                fs::remove_file(&path)?;
+                continue;
+            }
+            queue.disk_bytes += file_len;
+            queue.segments.push_back(Segment {
+                id,
+                file_len,
+                records,
+            });
+        }
+        Ok(queue)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn config(&self) -> &DiskQueueConfig {
+        &self.config
+    }
+
+    /// Encode `batch` with its highest precision and append it.
+    ///
+    /// Returns number of older batches removed to stay below
+    /// [`max_disk_bytes`](DiskQueueConfig::max_disk_bytes). Empty batch is not stored.
+    pub fn push(&mut self, batch: &Batch) -> io::Result<usize> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        let precision = batch.precision().unwrap_or_default();
+        self.push_payload(&batch.to_line_protocol_lossy(Some(precision)), precision)
+    }
+
+    /// Append already encoded `payload`. See [`push`](DiskQueue::push).
+    pub fn push_payload(&mut self, payload: &str, precision: Precision) -> io::Result<usize> {
+        let payload_len = u32::try_from(payload.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "batch is larger than 4GiB record limit",
+            )
+        })?;
+        let record_len = HEADER_LEN + payload.len() as u64;
+        if record_len > self.config.max_disk_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "batch is larger than disk quota",
+            ));
+        }
+
+        let mut dropped = 0;
+        while self.disk_bytes + record_len > self.config.max_disk_bytes {
+            match self.remove_front_segment()? {
+                Some(records) => dropped += records,
+                None => break,
+            }
+        }
+
+        let needs_segment = match self.segments.back() {
+            Some(segment) => segment.file_len >= self.config.max_segment_bytes,
+            None => true,
+        };
+        if needs_segment || self.writer.is_none() {
+            self.open_writer(needs_segment)?;
+        }
+
+        let mut record = Vec::with_capacity(record_len as usize);
+        record.extend_from_slice(&payload_len.to_le_bytes());
+        record.extend_from_slice(&checksum(precision, payload).to_le_bytes());
+        record.push(precision_to_byte(precision));
+        record.extend_from_slice(payload.as_bytes());
+
+        let writer = self.writer.as_mut().expect("writer is open");
+        writer.write_all(&record)?;
+        if self.config.sync {
+            writer.sync_data()?;
+        }
+        let segment = self.segments.back_mut().expect("segment is open");
+        segment.records.push_back(Record {
+            offset: segment.file_len,
+            len: record_len,
+        });
+        segment.file_len += record_len;
+        self.disk_bytes += record_len;
+        Ok(dropped)
+    }
+
+    /// Read the oldest batch without removing it.
+    pub fn front(&self) -> io::Result<Option<QueuedPayload>> {
+        let (segment, record) = match self
+            .segments
+            .front()
+            .and_then(|s| s.records.front().map(|r| (s, r)))
+        {
+            Some(front) => front,
+            None => return Ok(None),
+        };
+        let mut file = File::open(self.segment_path(segment.id))?;
+        file.seek(SeekFrom::Start(record.offset))?;
+        let mut buf = vec![0; record.len as usize];
+        file.read_exact(&mut buf)?;
+        parse_record(&buf)
+            .map(|(payload, _)| Some(payload))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "damaged record"))
+    }
+
+    /// Remove the oldest batch, usually after it was written.
+    pub fn pop_front(&mut self) -> io::Result<()> {
+        let segment = match self.segments.front_mut() {
+            Some(segment) => segment,
+            None => return Ok(()),
+        };
+        segment.records.pop_front();
+        match segment.records.front() {
+            Some(next) => write_cursor(&self.dir, segment.id, next.offset),
+            None => self.remove_front_segment().map(|_| ()),
+        }
+    }
+
+    /// Pass batches to `write` from the oldest one and remove each written batch.
+    ///
+    /// Stops at first error, the failed batch stays in queue. Returns number of written batches.
+    pub fn replay<F, E>(&mut self, mut write: F) -> Result<usize, E>
+    where
+        F: FnMut(&QueuedPayload) -> Result<(), E>,
+        E: From<io::Error>,
+    {
+        let mut written = 0;
+        while let Some(payload) = self.front()? {
+            write(&payload)?;
+            self.pop_front()?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Number of queued batches.
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.records.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(|s| s.records.is_empty())
+    }
+
+    /// Bytes of queued records.
+    pub fn backlog_bytes(&self) -> u64 {
+        self.segments
+            .iter()
+            .flat_map(|s| s.records.iter())
+            .map(|r| r.len)
+            .sum()
+    }
+
+    /// Size of all segment files, including already replayed records of the oldest one.
+    pub fn disk_bytes(&self) -> u64 {
+        self.disk_bytes
+    }
+
+    /// Number of segments with damaged records found when queue was opened.
+    pub fn damaged_segments(&self) -> usize {
+        self.skipped
+    }
+
+    fn segment_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+    }
+
+    fn open_writer(&mut self, new_segment: bool) -> io::Result<()> {
+        if new_segment {
+            self.segments.push_back(Segment {
+                id: self.next_id,
+                file_len: 0,
+                records: VecDeque::new(),
+            });
+            self.next_id += 1;
+        }
+        let id = self.segments.back().expect("segment exists").id;
+        self.writer = Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.segment_path(id))?,
+        );
+        Ok(())
+    }
+
+    // returns number of removed records
+    fn remove_front_segment(&mut self) -> io::Result<Option<usize>> {
+        let segment = match self.segments.pop_front() {
+            Some(segment) => segment,
+            None => return Ok(None),
+        };
+        if self.segments.is_empty() {
+            self.writer = None;
+        }
+        fs::remove_file(self.segment_path(segment.id))?;
+        self.disk_bytes -= segment.file_len;
+        match self
+            .segments
+            .front()
+            .and_then(|s| s.records.front().map(|r| (s.id, r)))
+        {
+            Some((id, record)) => write_cursor(&self.dir, id, record.offset)?,
+            None => write_cursor(&self.dir, self.next_id, 0)?,
+        }
+        Ok(Some(segment.records.len()))
+    }
+}
+
+fn checksum(precision: Precision, payload: &str) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[precision_to_byte(precision)]);
+    hasher.update(payload.as_bytes());
+    hasher.finalize()
+}
+
+fn precision_to_byte(precision: Precision) -> u8 {
+    match precision {
+        Precision::Secs => 0,
+        Precision::Milli => 1,
+        Precision::Micro => 2,
+        Precision::Nanos => 3,
+    }
+}
+
+fn precision_from_byte(byte: u8) -> Option<Precision> {
+    Some(match byte {
+        0 => Precision::Secs,
+        1 => Precision::Milli,
+        2 => Precision::Micro,
+        3 => Precision::Nanos,
+        _ => return None,
+    })
+}
+
+// parse record at start of `buf`, returns it with its length
+fn parse_record(buf: &[u8]) -> Option<(QueuedPayload, u64)> {
+    if (buf.len() as u64) < HEADER_LEN {
+        return None;
+    }
+    let payload_len = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    let expected = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    let precision = precision_from_byte(buf[8])?;
+    let payload = buf.get(HEADER_LEN as usize..HEADER_LEN as usize + payload_len)?;
+    let payload = std::str::from_utf8(payload).ok()?;
+    if checksum(precision, payload) != expected {
+        return None;
+    }
+    let payload = QueuedPayload {
+        payload: payload.to_string(),
+        precision,
+    };
+    Some((payload, HEADER_LEN + payload_len as u64))
+}
+
+// valid records, length of valid part and whether anything after it was cut off
+fn scan_segment(path: &Path) -> io::Result<(Vec<Record>, u64, bool)> {
+    let buf = fs::read(path)?;
+    let mut records = vec![];
+    let mut offset = 0;
+    while let Some((_, len)) = parse_record(&buf[offset as usize..]) {
+        records.push(Record { offset, len });
+        offset += len;
+    }
+    let damaged = offset < buf.len() as u64;
+    if damaged {
+        // following appends must start right after the last valid record
+        OpenOptions::new().write(true).open(path)?.set_len(offset)?;
+    }
+    Ok((records, offset, damaged))
+}
+
+fn read_cursor(dir: &Path) -> io::Result<Option<(u64, u64)>> {
+    let text = match fs::read_to_string(dir.join(CURSOR_FILE)) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let mut parts = text.split_whitespace().map(|p| p.parse::<u64>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(id), Some(offset)) => Ok(Some((id, offset))),
+        _ => Ok(None),
+    }
+}
+
+fn write_cursor(dir: &Path, id: u64, offset: u64) -> io::Result<()> {
+    // rename is atomic, so cursor is never half written
+    let tmp = dir.join(format!("{}.tmp", CURSOR_FILE));
+    fs::write(&tmp, format!("{} {}", id, offset))?;
+    fs::rename(tmp, dir.join(CURSOR_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::WriteError, test_util::point};
+
+    fn batch(from: i64, to: i64) -> Batch {
+        (from..to).map(point).collect()
+    }
+
+    fn payload(from: i64, to: i64) -> QueuedPayload {
+        QueuedPayload {
+            payload: batch(from, to).to_line_protocol_lossy(Some(Precision::Secs)),
+            precision: Precision::Secs,
+        }
+    }
+
+    fn small_segments() -> DiskQueueConfig {
+        DiskQueueConfig {
+            max_segment_bytes: 40,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replay_in_order_across_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = DiskQueue::open(dir.path(), small_segments()).unwrap();
+        for i in 0..5 {
+            assert_eq!(queue.push(&batch(i * 2, i * 2 + 2)).unwrap(), 0);
+        }
+        queue.push(&Batch::default()).unwrap();
+        assert_eq!(queue.len(), 5);
+        assert!(queue.segments.len() > 1);
+        assert_eq!(queue.front().unwrap(), Some(payload(0, 2)));
+
+        let mut written = vec![];
+        let result = queue.replay(|p| {
+            if written.len() == 3 {
+                return Err(WriteError::Transport("down".into()));
+            }
+            written.push(p.clone());
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(written, vec![payload(0, 2), payload(2, 4), payload(4, 6)]);
+        assert_eq!(queue.len(), 2);
+
+        let replayed = queue.replay(|_| Ok::<_, io::Error>(())).unwrap();
+        assert_eq!(replayed, 2);
+        assert!(queue.is_empty());
+        assert_eq!(queue.disk_bytes(), 0);
+        assert_eq!(queue.backlog_bytes(), 0);
+    }
+
+    #[test]
+    fn survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = DiskQueue::open(dir.path(), small_segments()).unwrap();
+        for i in 0..4 {
+            queue.push(&batch(i, i + 1)).unwrap();
+        }
+        queue.pop_front().unwrap();
+        let backlog = queue.backlog_bytes();
+        drop(queue);
+
+        let mut queue = DiskQueue::open(dir.path(), small_segments()).unwrap();
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.backlog_bytes(), backlog);
+        assert_eq!(queue.front().unwrap(), Some(payload(1, 2)));
+
+        queue.push(&batch(4, 5)).unwrap();
+        let mut replayed = vec![];
+        queue
+            .replay(|p| {
+                replayed.push(p.payload.clone());
+                Ok::<_, io::Error>(())
+            })
+            .unwrap();
+        assert_eq!(
+            replayed,
+            vec!["m v=1i 1", "m v=2i 2", "m v=3i 3", "m v=4i 4"]
+        );
+
+        let queue = DiskQueue::open(dir.path(), small_segments()).unwrap();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn reopen_drained_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = DiskQueue::open(dir.path(), DiskQueueConfig::default()).unwrap();
+        for i in 0..3 {
+            queue.push(&batch(i, i + 1)).unwrap();
+        }
+        queue.replay(|_| Ok::<_, io::Error>(())).unwrap();
+        drop(queue);
+
+        let mut queue = DiskQueue::open(dir.path(), DiskQueueConfig::default()).unwrap();
+        queue.push(&batch(3, 4)).unwrap();
+        assert_eq!(queue.len(), 1);
+        drop(queue);
+
+        let queue = DiskQueue::open(dir.path(), DiskQueueConfig::default()).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.front().unwrap(), Some(payload(3, 4)));
+    }
+
+    #[test]
+    fn skip_damaged_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = DiskQueue::open(dir.path(), DiskQueueConfig::default()).unwrap();
+        queue.push(&batch(0, 1)).unwrap();
+        queue.push(&batch(1, 2)).unwrap();
+        let path = queue.segment_path(0);
+        drop(queue);
+
+        // half written record and flipped byte of the second record
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        data.extend_from_slice(&[5, 0, 0]);
+        fs::write(&path, data).unwrap();
+
+        let mut queue = DiskQueue::open(dir.path(), DiskQueueConfig::default()).unwrap();
+        assert_eq!(queue.damaged_segments(), 1);
+        assert_eq!(queue.len(), 1);
+        queue.push(&batch(2, 3)).unwrap();
+        drop(queue);
+
+        let mut queue = DiskQueue::open(dir.path(), DiskQueueConfig::default()).unwrap();
+        assert_eq!(queue.damaged_segments(), 0);
+        assert_eq!(queue.front().unwrap(), Some(payload(0, 1)));
+        queue.pop_front().unwrap();
+        assert_eq!(queue.front().unwrap(), Some(payload(2, 3)));
+    }
+
+    #[test]
+    fn quota_drops_oldest_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        // every record takes 9 + 8 bytes, one record per segment
+        let config = DiskQueueConfig {
+            max_segment_bytes: 1,
+            max_disk_bytes: 17 * 3,
+            sync: true,
+        };
+        let mut queue = DiskQueue::open(dir.path(), config).unwrap();
+        for i in 0..3 {
+            assert_eq!(queue.push(&batch(i, i + 1)).unwrap(), 0);
+        }
+        assert_eq!(queue.disk_bytes(), 17 * 3);
+        assert_eq!(queue.push(&batch(3, 4)).unwrap(), 1);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.disk_bytes(), 17 * 3);
+        assert_eq!(queue.front().unwrap(), Some(payload(1, 2)));
+
+        assert!(queue.push(&batch(0, 10)).is_err());
+        drop(queue);
+        let queue = DiskQueue::open(dir.path(), config).unwrap();
+        assert_eq!(queue.front().unwrap(), Some(payload(1, 2)));
+    }
+}
@@ -0,0 +1,334 @@
+use super::{
+    batch::default_tag_set, error::Error, Batch, CompressedPayload, Compression, Field, Point,
+    PointTemplate, Precision, Tag, TagSet, Timestamp,
+};
+use std::iter::FromIterator;
+use std::{borrow::Borrow, io};
+
+/// A collection of points already encoded in InfluxDB line protocol format.
+///
+/// Each point is escaped and appended to one contiguous buffer when it is pushed, so no [`Point`]
+/// is kept in memory. Precision and default tags must be set before first point is pushed.
+#[derive(Debug, Clone, Default)]
+pub struct EncodedBatch {
+    buf: String,
+    // start of each line in `buf`
+    line_offsets: Vec<usize>,
+    precision: Option<Precision>,
+    default_tags: TagSet,
+    // last template passed to `push_template` and the same template with default tags
+    tagged_template: Option<(PointTemplate, PointTemplate)>,
+}
+
+impl EncodedBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create batch with buffer of `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: String::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    /// Encode all following points with `precision` (see [`Batch::to_line_protocol_lossy`]).
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Set tags added to every following point. See [`Batch::with_default_tags`].
+    pub fn with_default_tags<I>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Tag>,
+    {
+        self.default_tags = default_tag_set(tags);
+        self.tagged_template = None;
+        self
+    }
+
+    pub fn precision(&self) -> Option<Precision> {
+        self.precision
+    }
+
+    pub fn default_tags(&self) -> &[Tag] {
+        &self.default_tags
+    }
+
+    pub fn push_point(&mut self, p: impl Into<Point>) {
+        let point = p.into();
+        if !self.line_offsets.is_empty() {
+            self.buf.push('\n');
+        }
+        self.line_offsets.push(self.buf.len());
+        point.write_text(&mut self.buf, self.precision, &self.default_tags);
+    }
+
+    /// Append point created from `template`, `fields` and `timestamp`.
+    ///
+    /// Default tags are added to tag set of `template`. The merged template is cached, so
+    /// pushing many points with the same template escapes its tags once.
+    pub fn push_template<I>(
+        &mut self,
+        template: &PointTemplate,
+        fields: I,
+        timestamp: impl Into<Timestamp>,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Field>,
+    {
+        let start = self.buf.len();
+        let line_start = if self.line_offsets.is_empty() {
+            start
+        } else {
+            self.buf.push('\n');
+            start + 1
+        };
+        let timestamp = timestamp.into();
+        let timestamp = self
+            .precision
+            .map(|p| timestamp.timestamp_precision_lossy(p))
+            .unwrap_or(timestamp);
+        let template = if self.default_tags.is_empty() {
+            template
+        } else {
+            let cached = match &self.tagged_template {
+                Some((t, _)) => t == template,
+                None => false,
+            };
+            if !cached {
+                let tagged = template.with_default_tags(&self.default_tags);
+                self.tagged_template = Some((template.clone(), tagged));
+            }
+            match &self.tagged_template {
+                Some((_, tagged)) => tagged,
+                None => unreachable!("template is cached above"),
+            }
+        };
+        match template.write(fields, timestamp, &mut self.buf) {
+            Ok(()) => {
+                self.line_offsets.push(line_start);
+                Ok(())
+            }
+            Err(err) => {
+                self.buf.truncate(start);
+                Err(err)
+            }
+        }
+    }
+
+    /// Number of points.
+    pub fn len(&self) -> usize {
+        self.line_offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.line_offsets.is_empty()
+    }
+
+    /// Size of encoded payload in bytes.
+    pub fn byte_len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Start of each line in [`as_str`](EncodedBatch::as_str).
+    pub fn line_offsets(&self) -> &[usize] {
+        &self.line_offsets
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.buf
+    }
+
+    /// Get line of point at position `index`.
+    pub fn line(&self, index: usize) -> Option<&str> {
+        let start = *self.line_offsets.get(index)?;
+        let end = self
+            .line_offsets
+            .get(index + 1)
+            .map(|next| next - 1)
+            .unwrap_or_else(|| self.buf.len());
+        Some(&self.buf[start..end])
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        (0..self.len()).filter_map(move |i| self.line(i))
+    }
+
+    /// Split payload into parts of whole lines that are at most `max_bytes` long.
+    ///
+    /// Line longer than `max_bytes` is returned as a single part.
+    pub fn chunks(&self, max_bytes: usize) -> impl Iterator<Item = &str> {
+        let mut index = 0;
+        std::iter::from_fn(move || {
+            let start = *self.line_offsets.get(index)?;
+            let mut end = self.line(index)?.len() + start;
+            index += 1;
+            while let Some(line) = self.line(index) {
+                let next_end = self.line_offsets[index] + line.len();
+                if next_end - start > max_bytes {
+                    break;
+                }
+                end = next_end;
+                index += 1;
+            }
+            Some(&self.buf[start..end])
+        })
+    }
+
+    /// Compress current payload without taking it.
+    pub fn to_compressed(&self, compression: Compression) -> io::Result<CompressedPayload> {
+        compression.compress(self.buf.as_bytes())
+    }
+
+    /// Hand out encoded payload and reset this batch. Precision and default tags are kept.
+    ///
+    /// Works like [`Batch::clone_and_clear`] but without encoding points again.
+    pub fn take_payload(&mut self) -> String {
+        self.line_offsets.clear();
+        let capacity = self.buf.capacity();
+        std::mem::replace(&mut self.buf, String::with_capacity(capacity))
+    }
+}
+
+impl From<Batch> for EncodedBatch {
+    fn from(batch: Batch) -> Self {
+        let mut encoded = Self::new().with_default_tags(batch.default_tags().to_vec());
+        encoded.extend(batch);
+        encoded
+    }
+}
+
+impl<P> FromIterator<P> for EncodedBatch
+where
+    P: Into<Point>,
+{
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        let mut encoded = Self::new();
+        encoded.extend(iter);
+        encoded
+    }
+}
+
+impl<P> Extend<P> for EncodedBatch
+where
+    P: Into<Point>,
+{
+    fn extend<I: IntoIterator<Item = P>>(&mut self, iter: I) {
+        for point in iter {
+            self.push_point(point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Timestamp;
+    use std::convert::TryFrom;
+
+    fn point(m: &str, ts: Timestamp) -> Point {
+        Point::builder(m)
+            .unwrap()
+            .try_add_tag(("host", "a"))
+            .try_add_field(("value", 1i64))
+            .timestamp(ts)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn encoded_same_as_batch() {
+        let batch = Batch::from(vec![
+            point("cpu", Timestamp::Nanos(1_500_000_000)),
+            point("mem", Timestamp::Now),
+        ])
+        .with_default_tags(vec![Tag::try_from(("region", "eu")).unwrap()]);
+
+        let mut encoded = EncodedBatch::from(batch.clone());
+        assert_eq!(encoded.len(), 2);
+        assert_eq!(encoded.as_str(), batch.to_line_protocol_lossy(None));
+        assert_eq!(encoded.byte_len(), batch.stats().encoded_len);
+        assert_eq!(encoded.line(1), Some("mem,host=a,region=eu value=1i"));
+        assert_eq!(encoded.line(2), None);
+
+        let payload = encoded.take_payload();
+        assert_eq!(payload, batch.to_line_protocol_lossy(None));
+        assert!(encoded.is_empty());
+        assert_eq!(encoded.as_str(), "");
+        assert_eq!(encoded.default_tags().len(), 1);
+
+        // default tags of `batch` are not added by `extend`
+        let mut encoded = EncodedBatch::new().with_precision(Precision::Secs);
+        encoded.extend(batch);
+        assert_eq!(
+            encoded.lines().collect::<Vec<_>>(),
+            vec!["cpu,host=a value=1i 1", "mem,host=a value=1i"]
+        );
+    }
+
+    #[test]
+    fn chunks() {
+        let encoded = (0..5)
+            .map(|i| point("m", Timestamp::Secs(i)))
+            .collect::<EncodedBatch>();
+        // every line is 19 bytes long
+        assert_eq!(encoded.line_offsets(), &[0, 20, 40, 60, 80]);
+
+        let chunks = encoded.chunks(41).collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.join("\n"), encoded.as_str());
+        assert!(chunks.iter().all(|c| c.len() <= 41));
+
+        assert_eq!(encoded.chunks(1).count(), 5);
+        assert_eq!(encoded.chunks(usize::MAX).count(), 1);
+        assert_eq!(EncodedBatch::new().chunks(10).count(), 0);
+    }
+
+    #[test]
+    fn push_template() {
+        let template = PointTemplate::new("m", vec![("host", "a")]).unwrap();
+        let fields = vec![Field::new("value", 1i64).unwrap()];
+        let mut encoded = EncodedBatch::new().with_precision(Precision::Secs);
+        encoded.push_point(point("m", Timestamp::Secs(0)));
+        encoded
+            .push_template(&template, &fields, Timestamp::Milli(1000))
+            .unwrap();
+        assert!(encoded
+            .push_template(&template, Vec::<Field>::new(), Timestamp::Now)
+            .is_err());
+
+        assert_eq!(encoded.len(), 2);
+        assert_eq!(encoded.as_str(), "m,host=a value=1i 0\nm,host=a value=1i 1");
+        assert_eq!(encoded.line(1), Some("m,host=a value=1i 1"));
+    }
+
+    #[test]
+    fn push_template_with_default_tags() {
+        let template = PointTemplate::new("m", vec![("host", "a")]).unwrap();
+        let other = PointTemplate::new("n", Vec::<Tag>::new()).unwrap();
+        let fields = vec![Field::new("value", 1i64).unwrap()];
+        let tags = vec![("region", "eu"), ("host", "default")]
+            .into_iter()
+            .map(|t| Tag::try_from(t).unwrap());
+        let mut encoded = EncodedBatch::new().with_default_tags(tags);
+        // point and template with the same tags give the same line
+        encoded.push_point(point("m", Timestamp::Now));
+        for template in &[&template, &template, &other] {
+            encoded
+                .push_template(template, &fields, Timestamp::Now)
+                .unwrap();
+        }
+        assert_eq!(
+            encoded.as_str(),
+            "m,host=a,region=eu value=1i\n\
+             m,host=a,region=eu value=1i\n\
+             m,host=a,region=eu value=1i\n\
+             n,host=default,region=eu value=1i"
+        );
+    }
+}
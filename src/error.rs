@@ -1,5 +1,6 @@
+use super::UdpReport;
 use ordered_float::FloatIsNan;
-use std::convert::Infallible;
+use std::{convert::Infallible, sync::Arc, time::Duration};
 use thiserror::Error;
 
 #[derive(Debug, Error, Clone)]
@@ -8,8 +9,338 @@ pub enum Error {
     NewLine,
     #[error("Start meassurment, field key or tag key with `_` is forbiden")]
     StartWithForbieden_,
+    #[error("At least one field value is required")]
+    NoField,
+    #[error("Invalid line protocol: {}", .0)]
+    Parse(String),
+    #[error("Float field value must be finite")]
+    InfiniteFloat,
     #[error("{}", .0)]
     Infallible(#[from] Infallible),
     #[error("{}", .0)]
     FloatIsNan(#[from] FloatIsNan),
 }
+
+/// Errors of preparing or sending a write to InfluxDB.
+///
+/// [External doc](https://v2.docs.influxdata.com/v2.0/api/#operation/PostWrite)
+#[derive(Debug, Error, Clone)]
+pub enum WriteError {
+    #[error("{}", .0)]
+    Io(Arc<std::io::Error>),
+    #[cfg(feature = "http")]
+    #[error("{}", .0)]
+    Http(Arc<http1::Error>),
+    #[error("Transport error: {}", .0)]
+    Transport(String),
+    /// Writer was shut down.
+    #[error("Writer is closed")]
+    Closed,
+    /// Line protocol is malformed. Some points may have been written.
+    #[error("Bad request: {}", .message)]
+    BadRequest { message: String },
+    #[error("Unauthorized: {}", .message)]
+    Unauthorized { message: String },
+    /// Organization or bucket does not exist.
+    #[error("Not found: {}", .message)]
+    NotFound { message: String },
+    /// Some lines were rejected, other points may have been written.
+    #[error("Partial write: {}", .message)]
+    PartialWrite {
+        message: String,
+        errors: Vec<LineError>,
+    },
+    #[error("Payload too large: {}", .message)]
+    PayloadTooLarge { message: String },
+    #[error("Too many requests (retry after {:?})", .retry_after)]
+    TooManyRequests { retry_after: Option<Duration> },
+    #[error("Service unavailable (retry after {:?})", .retry_after)]
+    ServiceUnavailable { retry_after: Option<Duration> },
+    #[error("Unexpected status {}: {}", .status, .message)]
+    UnexpectedStatus { status: u16, message: String },
+}
+
+impl WriteError {
+    /// Map response of write endpoint. Returns `None` for success status.
+    ///
+    /// `retry_after` is value of `Retry-After` header.
+    /// Error bodies of InfluxDB v1, v2 and v3 are understood. `400` and `422` responses that
+    /// point at rejected lines become [`PartialWrite`](WriteError::PartialWrite). Without
+    /// `serde_json` feature (enabled by `http` and `testing`) the whole body is the message.
+    pub fn from_response(status: u16, body: &str, retry_after: Option<&str>) -> Option<Self> {
+        let (message, errors) = parse_error_body(body);
+        let retry_after = retry_after.and_then(parse_retry_after);
+        Some(match status {
+            200..=299 => return None,
+            400 | 422 if !errors.is_empty() => WriteError::PartialWrite { message, errors },
+            400 => WriteError::BadRequest { message },
+            401 => WriteError::Unauthorized { message },
+            404 => WriteError::NotFound { message },
+            413 => WriteError::PayloadTooLarge { message },
+            429 => WriteError::TooManyRequests { retry_after },
+            503 => WriteError::ServiceUnavailable { retry_after },
+            status => WriteError::UnexpectedStatus { status, message },
+        })
+    }
+
+    /// Whether the same write may succeed later: throttling, server errors and connection
+    /// failures.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            WriteError::Io(_)
+            | WriteError::Transport(_)
+            | WriteError::TooManyRequests { .. }
+            | WriteError::ServiceUnavailable { .. } => true,
+            WriteError::UnexpectedStatus { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// Lines rejected by server.
+    pub fn line_errors(&self) -> &[LineError] {
+        match self {
+            WriteError::PartialWrite { errors, .. } => errors,
+            _ => &[],
+        }
+    }
+
+    /// Time server asked to wait before next write.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            WriteError::TooManyRequests { retry_after }
+            | WriteError::ServiceUnavailable { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Errors of [`LineProtocolCodec`](crate::LineProtocolCodec).
+#[cfg(feature = "codec")]
+#[derive(Debug, Error)]
+pub enum CodecError {
+    /// Line is longer than configured maximum. It is skipped up to the next newline.
+    #[error("Line is longer than {} bytes", .max)]
+    LineTooLong { max: usize },
+    /// Line could not be parsed. It is skipped.
+    #[error("Invalid line `{}`: {}", .line, .error)]
+    InvalidLine { line: String, error: Error },
+    /// Reading or writing the underlying stream failed.
+    #[error("{}", .0)]
+    Io(#[from] std::io::Error),
+}
+
+/// Send error of [`UdpWriter`](crate::UdpWriter) with datagrams sent before it.
+#[derive(Debug, Error)]
+#[error("{}", .error)]
+pub struct UdpWriteError {
+    #[source]
+    pub error: std::io::Error,
+    /// What was sent before the error. Lines of the failed datagram and after it were not sent.
+    pub report: UdpReport,
+}
+
+impl From<UdpWriteError> for std::io::Error {
+    fn from(err: UdpWriteError) -> Self {
+        err.error
+    }
+}
+
+/// Line of payload rejected by server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineError {
+    /// Line number counted from 1.
+    pub line: Option<usize>,
+    /// Rejected line as sent.
+    pub original_line: Option<String>,
+    pub reason: String,
+}
+
+impl From<std::io::Error> for WriteError {
+    fn from(err: std::io::Error) -> Self {
+        WriteError::Io(Arc::new(err))
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<http1::Error> for WriteError {
+    fn from(err: http1::Error) -> Self {
+        WriteError::Http(Arc::new(err))
+    }
+}
+
+// only delay in seconds is supported, InfluxDB doesn't send HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+// message and rejected lines of v1 `{"error": ..}`, v2 `{"code": .., "message": ..}` or
+// v3 `{"error": .., "data": [{"line_number": .., "original_line": .., "error_message": ..}]}`
+#[cfg(feature = "serde_json")]
+fn parse_error_body(body: &str) -> (String, Vec<LineError>) {
+    let json = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(json @ serde_json::Value::Object(_)) => json,
+        _ => return (body.to_string(), parse_line_errors(body)),
+    };
+    let message = json
+        .get("message")
+        .or_else(|| json.get("error"))
+        .and_then(|m| m.as_str())
+        .unwrap_or(body)
+        .to_string();
+
+    let data = match json.get("data") {
+        Some(serde_json::Value::Array(data)) => data.iter().collect(),
+        Some(data @ serde_json::Value::Object(_)) => vec![data],
+        _ => vec![],
+    };
+    let errors = data
+        .into_iter()
+        .map(|e| LineError {
+            line: e
+                .get("line_number")
+                .and_then(|n| n.as_u64())
+                .map(|n| n as usize),
+            original_line: e
+                .get("original_line")
+                .and_then(|l| l.as_str())
+                .map(String::from),
+            reason: e
+                .get("error_message")
+                .and_then(|m| m.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        })
+        .filter(|e| e.line.is_some() || e.original_line.is_some())
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        let errors = parse_line_errors(&message);
+        (message, errors)
+    } else {
+        (message, errors)
+    }
+}
+
+// v1 and v2 list errors in message, one per line:
+// `unable to parse '<line>': <reason>` or `error parsing line 3 (1-based): <reason>`
+#[cfg(feature = "serde_json")]
+fn parse_line_errors(message: &str) -> Vec<LineError> {
+    message
+        .lines()
+        .filter_map(|part| {
+            let mut error = LineError {
+                line: None,
+                original_line: None,
+                reason: String::new(),
+            };
+            let rest = if let Some(start) = part.find("unable to parse '") {
+                let quoted = &part[start + "unable to parse '".len()..];
+                let end = quoted.rfind("': ")?;
+                error.original_line = Some(quoted[..end].to_string());
+                &quoted[end + 3..]
+            } else {
+                let start = part.find("line ")? + "line ".len();
+                let digits = part[start..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .map_or(&part[start..], |end| &part[start..start + end]);
+                error.line = Some(digits.parse().ok()?);
+                let rest = &part[start + digits.len()..];
+                rest.find(": ").map_or("", |i| &rest[i + 2..])
+            };
+            // v1 appends number of dropped points
+            let reason = match rest.rfind(" dropped=") {
+                Some(i) if rest[i + " dropped=".len()..].parse::<usize>().is_ok() => &rest[..i],
+                _ => rest,
+            };
+            error.reason = reason.trim().to_string();
+            Some(error)
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "serde_json"))]
+fn parse_error_body(body: &str) -> (String, Vec<LineError>) {
+    (body.to_string(), vec![])
+}
+
+#[cfg(all(test, feature = "serde_json"))]
+mod tests {
+    use super::*;
+
+    fn line_error(line: Option<usize>, original_line: Option<&str>, reason: &str) -> LineError {
+        LineError {
+            line,
+            original_line: original_line.map(String::from),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn v1_body() {
+        let body = r#"{"error":"partial write: unable to parse 'cpu,host=a value=': missing field value dropped=0"}"#;
+        let err = WriteError::from_response(400, body, None).unwrap();
+        assert_eq!(
+            err.line_errors(),
+            &[line_error(
+                None,
+                Some("cpu,host=a value="),
+                "missing field value"
+            )]
+        );
+    }
+
+    #[test]
+    fn v2_body() {
+        let body = r#"{"code":"invalid","message":"failed to parse line protocol:\nerror parsing line 2 (1-based): Invalid measurement\nerror parsing line 17 (1-based): Could not parse entire line"}"#;
+        match WriteError::from_response(400, body, None).unwrap() {
+            WriteError::PartialWrite { message, errors } => {
+                assert!(message.starts_with("failed to parse line protocol"));
+                assert_eq!(
+                    errors,
+                    vec![
+                        line_error(Some(2), None, "Invalid measurement"),
+                        line_error(Some(17), None, "Could not parse entire line"),
+                    ]
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let body = r#"{"code":"invalid","message":"unable to parse 'a b': invalid field format"}"#;
+        let err = WriteError::from_response(422, body, None).unwrap();
+        assert_eq!(
+            err.line_errors(),
+            &[line_error(None, Some("a b"), "invalid field format")]
+        );
+    }
+
+    #[test]
+    fn v3_body() {
+        let body = r#"{"error":"partial write of line protocol occurred","data":[{"original_line":"dskf","line_number":1,"error_message":"No fields were provided"},{"original_line":"cpu f=1i 1","line_number":3,"error_message":"invalid column type"}]}"#;
+        let err = WriteError::from_response(400, body, None).unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Partial write: partial write of line protocol occurred"
+        );
+        assert_eq!(
+            err.line_errors(),
+            &[
+                line_error(Some(1), Some("dskf"), "No fields were provided"),
+                line_error(Some(3), Some("cpu f=1i 1"), "invalid column type"),
+            ]
+        );
+    }
+
+    #[test]
+    fn without_line_errors() {
+        match WriteError::from_response(400, r#"{"code":"invalid","message":"bad"}"#, None) {
+            Some(WriteError::BadRequest { message }) => assert_eq!(message, "bad"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match WriteError::from_response(401, "plain text", None) {
+            Some(WriteError::Unauthorized { message }) => assert_eq!(message, "plain text"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(WriteError::from_response(204, "", None).is_none());
+    }
+}
@@ -25,6 +25,15 @@ where
     escaped_string
 }
 
+/// Length in bytes of `s` after escaping, without building escaped string.
+#[inline]
+fn escaped_len<P>(is_escape_char: P, s: &str) -> usize
+where
+    P: Fn(char) -> bool,
+{
+    s.len() + s.chars().filter(|c| is_escape_char(*c)).count()
+}
+
 #[inline]
 fn escape_comma_equal_space(c: char) -> bool {
     match c {
@@ -48,26 +57,46 @@ pub fn tag_value(s: &str) -> String {
     escape(escape_comma_equal_space, s)
 }
 
+#[inline]
+fn escape_quote_backslash(c: char) -> bool {
+    match c {
+        '"' | '\\' => true,
+        _c => false,
+    }
+}
+
+#[inline]
+fn escape_comma_space(c: char) -> bool {
+    match c {
+        ',' | ' ' => true,
+        _c => false,
+    }
+}
+
 #[inline]
 pub fn field_value(s: &str) -> String {
-    escape(
-        |c| match c {
-            '"' | '\\' => true,
-            _c => false,
-        },
-        s,
-    )
+    escape(escape_quote_backslash, s)
 }
 
 #[inline]
 pub fn measurement(s: &str) -> String {
-    escape(
-        |c| match c {
-            ',' | ' ' => true,
-            _c => false,
-        },
-        s,
-    )
+    escape(escape_comma_space, s)
+}
+
+/// Length of [`tag_key`], [`tag_value`] or [`field_key`] output.
+#[inline]
+pub fn key_len(s: &str) -> usize {
+    escaped_len(escape_comma_equal_space, s)
+}
+
+#[inline]
+pub fn field_value_len(s: &str) -> usize {
+    escaped_len(escape_quote_backslash, s)
+}
+
+#[inline]
+pub fn measurement_len(s: &str) -> usize {
+    escaped_len(escape_comma_space, s)
 }
 
 #[cfg(all(feature = "nightly", test))]
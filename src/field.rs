@@ -2,6 +2,7 @@ use super::{
     error::Error,
     escape,
     name_restriction::{prevent_filed_value_string, prevent_key},
+    number,
 };
 use derive_more::{Deref, Display, From};
 use ordered_float::NotNan;
@@ -9,6 +10,7 @@ use ordered_float::NotNan;
 use std::{
     borrow::Borrow,
     convert::{TryFrom, TryInto},
+    sync::Arc,
 };
 
 #[cfg(feature = "serde")]
@@ -21,7 +23,8 @@ use serde1::{
 ///
 ///Field keys are strings and they store metadata.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash, Display, Deref)]
-pub struct FieldKey(String);
+#[deref(forward)]
+pub struct FieldKey(Arc<str>);
 
 #[cfg(feature = "serde")]
 impl Serialize for FieldKey {
@@ -29,7 +32,7 @@ impl Serialize for FieldKey {
     where
         S: Serializer,
     {
-        self.0.serialize(serializer)
+        serializer.serialize_str(&self.0)
     }
 }
 
@@ -49,7 +52,7 @@ impl FieldKey {
     pub fn new(s: impl Into<String>) -> Result<Self, Error> {
         let s = s.into();
         prevent_key(&s)?;
-        Ok(Self(s))
+        Ok(Self(s.into()))
     }
 }
 
@@ -151,7 +154,7 @@ impl<'de> Deserialize<'de> for FieldValue {
             where
                 E: de::Error,
             {
-                NotNan::try_from(v).map_err(E::custom).map(FieldValue::from)
+                FieldValue::try_from(v).map_err(E::custom)
             }
             fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
             where
@@ -186,9 +189,13 @@ impl From<u8> for FieldValue {
     }
 }
 
+/// InfluxDB accepts neither NaN nor infinite floats.
 impl TryFrom<f64> for FieldValue {
     type Error = Error;
     fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if value.is_infinite() {
+            return Err(Error::InfiniteFloat);
+        }
         NotNan::new(value)
             .map(FieldValue::from)
             .map_err(Error::from)
@@ -202,19 +209,76 @@ impl TryFrom<f32> for FieldValue {
     }
 }
 
+/// Type of [`FieldValue`] without the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FieldType {
+    String,
+    UInteger,
+    Integer,
+    Float,
+    Boolean,
+}
+
 impl FieldValue {
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldValue::String(_) => FieldType::String,
+            FieldValue::UInteger(_) => FieldType::UInteger,
+            FieldValue::Integer(_) => FieldType::Integer,
+            FieldValue::Float(_) => FieldType::Float,
+            FieldValue::Boolean(_) => FieldType::Boolean,
+        }
+    }
+
+    // length of `to_text` output
+    fn encoded_len(&self) -> usize {
+        match self {
+            FieldValue::String(s) => escape::field_value_len(s) + 2,
+            FieldValue::UInteger(i) => number::integer_len(*i) + 1,
+            FieldValue::Integer(i) => number::integer_len(*i) + 1,
+            FieldValue::Float(f) => number::float_len(f.into_inner()),
+            FieldValue::Boolean(b) => bool_text(*b).len(),
+        }
+    }
+
     // convert self to string according to docs: https://v2.docs.influxdata.com/v2.0/reference/syntax/line-protocol/
-    fn to_text(&self) -> String {
+    #[cfg(test)]
+    pub(crate) fn to_text(&self) -> String {
+        let mut out = String::with_capacity(self.encoded_len());
+        self.write_text(&mut out);
+        out
+    }
+
+    pub(crate) fn write_text(&self, out: &mut String) {
         match self {
-            FieldValue::String(s) => format!(r#""{}""#, escape::field_value(&s)),
-            FieldValue::UInteger(i) => i.to_string() + "u",
-            FieldValue::Integer(i) => i.to_string() + "i",
-            FieldValue::Float(f) => f.to_string(),
-            FieldValue::Boolean(b) => b.to_string(),
+            FieldValue::String(s) => {
+                out.push('"');
+                *out += &escape::field_value(s);
+                out.push('"');
+            }
+            FieldValue::UInteger(i) => {
+                number::push_integer(out, *i);
+                out.push('u');
+            }
+            FieldValue::Integer(i) => {
+                number::push_integer(out, *i);
+                out.push('i');
+            }
+            FieldValue::Float(f) => number::push_float(out, f.into_inner()),
+            FieldValue::Boolean(b) => out.push_str(bool_text(*b)),
         }
     }
 }
 
+#[inline]
+fn bool_text(b: bool) -> &'static str {
+    if b {
+        "true"
+    } else {
+        "false"
+    }
+}
+
 ///The key-value pair in an InfluxDB data structure that records metadata and the actual data value.
 ///
 ///Fields are required in InfluxDB data structures and they are not indexed - queries on field values
@@ -259,9 +323,33 @@ impl Field {
         })
     }
 
+    pub(crate) fn from_parts(key: FieldKey, value: FieldValue) -> Self {
+        Self { key, value }
+    }
+
+    pub fn key(&self) -> &FieldKey {
+        &self.key
+    }
+
+    pub fn value(&self) -> &FieldValue {
+        &self.value
+    }
+
+    #[cfg(test)]
     pub(crate) fn to_text(&self) -> String {
-        let key = escape::field_key(&self.key);
-        format!("{}={}", key, self.value.to_text())
+        let mut out = String::with_capacity(self.encoded_len());
+        self.write_text(&mut out);
+        out
+    }
+
+    pub(crate) fn write_text(&self, out: &mut String) {
+        *out += &escape::field_key(&self.key);
+        out.push('=');
+        self.value.write_text(out);
+    }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        escape::key_len(&self.key) + 1 + self.value.encoded_len()
     }
 }
 
@@ -303,6 +391,22 @@ mod tests {
         assert_eq!(fv.to_text(), r#"true"#);
     }
 
+    #[test]
+    fn encoded_len_match_text() {
+        let values: Vec<FieldValue> = vec![
+            FieldValue::try_from(r#"All = " \ , escaped characters"#).unwrap(),
+            (-64i64).into(),
+            64u64.into(),
+            NotNan::new(-0.1f64).unwrap().into(),
+            NotNan::new(1e21f64).unwrap().into(),
+            false.into(),
+        ];
+        for v in values {
+            let field = Field::new("a =b", v).unwrap();
+            assert_eq!(field.encoded_len(), field.to_text().len());
+        }
+    }
+
     #[test]
     fn escape_filed_set() {
         let fv = FieldValue::try_from(String::from(r#""\"#)).unwrap();
@@ -0,0 +1,146 @@
+use super::{
+    error::Error, Field, FieldKey, FieldValue, Measurement, PointBuilder, Tag, TagKey, TagValue,
+};
+use std::{
+    borrow::Borrow,
+    collections::HashSet,
+    convert::{TryFrom, TryInto},
+    hash::Hash,
+};
+
+/// Cache of validated names shared between points.
+///
+/// [`Measurement`], [`TagKey`], [`TagValue`] and [`FieldKey`] store their text in `Arc<str>`, so
+/// clone is cheap. `Interner` returns the same allocation for equal strings, so a million points
+/// with the same keys keep only one copy of each key. Name is validated only the first time it is
+/// seen.
+///
+/// Keep one interner per batch or share it between batches with your own synchronization.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    measurements: HashSet<Measurement>,
+    tag_keys: HashSet<TagKey>,
+    tag_values: HashSet<TagValue>,
+    field_keys: HashSet<FieldKey>,
+}
+
+fn intern<T>(set: &mut HashSet<T>, s: &str) -> Result<T, Error>
+where
+    T: for<'a> TryFrom<&'a str, Error = Error> + Borrow<str> + Hash + Eq + Clone,
+{
+    if let Some(value) = set.get(s) {
+        return Ok(value.clone());
+    }
+    let value = T::try_from(s)?;
+    set.insert(value.clone());
+    Ok(value)
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn measurement(&mut self, s: &str) -> Result<Measurement, Error> {
+        intern(&mut self.measurements, s)
+    }
+
+    pub fn tag_key(&mut self, s: &str) -> Result<TagKey, Error> {
+        intern(&mut self.tag_keys, s)
+    }
+
+    pub fn tag_value(&mut self, s: &str) -> Result<TagValue, Error> {
+        intern(&mut self.tag_values, s)
+    }
+
+    pub fn field_key(&mut self, s: &str) -> Result<FieldKey, Error> {
+        intern(&mut self.field_keys, s)
+    }
+
+    pub fn tag(&mut self, key: &str, value: &str) -> Result<Tag, Error> {
+        Ok(Tag::from_parts(self.tag_key(key)?, self.tag_value(value)?))
+    }
+
+    pub fn field<V>(&mut self, key: &str, value: V) -> Result<Field, Error>
+    where
+        V: TryInto<FieldValue>,
+        V::Error: Into<Error>,
+    {
+        let value = value.try_into().map_err(|e| e.into())?;
+        Ok(Field::from_parts(self.field_key(key)?, value))
+    }
+
+    /// Create [`PointBuilder`] with interned measurement.
+    pub fn builder(&mut self, measurement: &str) -> Result<PointBuilder, Error> {
+        Ok(PointBuilder::with_measurement(
+            self.measurement(measurement)?,
+        ))
+    }
+
+    /// Number of distinct interned strings.
+    pub fn len(&self) -> usize {
+        self.measurements.len()
+            + self.tag_keys.len()
+            + self.tag_values.len()
+            + self.field_keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.measurements.clear();
+        self.tag_keys.clear();
+        self.tag_values.clear();
+        self.field_keys.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_allocation() {
+        let mut interner = Interner::new();
+        let points = (0..3)
+            .map(|i| {
+                interner
+                    .builder("cpu")
+                    .unwrap()
+                    .add_tag(interner.tag("host", "a").unwrap())
+                    .add_field(interner.field("value", i as i64).unwrap())
+                    .build()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(interner.len(), 4);
+        let first = &points[0];
+        for p in &points[1..] {
+            assert!(std::ptr::eq(
+                p.measurement().as_ptr(),
+                first.measurement().as_ptr()
+            ));
+            assert!(std::ptr::eq(
+                p.tags()[0].value().as_ptr(),
+                first.tags()[0].value().as_ptr()
+            ));
+            assert!(std::ptr::eq(
+                p.fields()[0].key().as_ptr(),
+                first.fields()[0].key().as_ptr()
+            ));
+        }
+        assert_eq!(first.to_text_with_precision(None), "cpu,host=a value=0i");
+    }
+
+    #[test]
+    fn keep_validation() {
+        let mut interner = Interner::new();
+        assert!(interner.tag_key("_reserved").is_err());
+        assert!(interner.tag_value("new\nline").is_err());
+        assert!(interner.field("_f", 1i64).is_err());
+        assert!(interner.is_empty());
+    }
+}
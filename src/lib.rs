@@ -13,23 +13,89 @@
 
 mod escape;
 
+#[cfg(feature = "async-writer")]
+mod async_writer;
+
 mod batch;
+#[cfg(feature = "client-blocking")]
+mod blocking;
+#[cfg(feature = "codec")]
+mod codec;
+mod columnar;
+mod compress;
+mod dead_letter;
+#[cfg(feature = "disk-queue")]
+mod disk_queue;
+mod encoded;
 pub mod error;
 pub mod field;
+mod intern;
+#[cfg(feature = "testing")]
+mod lines_eq;
 mod measurement;
 mod name_restriction;
+mod number;
+mod parse;
 mod point;
 mod precision;
+#[cfg(feature = "testing")]
+mod recording;
+#[cfg(feature = "http")]
+mod request;
+mod retry;
+mod series;
+mod sink;
+mod stats;
+mod stream;
+mod sync;
 pub mod tag;
+mod template;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "testing")]
+mod testing;
 mod timestamp;
+mod udp;
 
-pub use batch::Batch;
-pub use field::{Field, FieldKey, FieldValue};
+#[cfg(feature = "async-writer")]
+pub use async_writer::{AsyncBatchWriter, AsyncBatchWriterSink, WriterConfig};
+pub use batch::{Batch, FieldConflict};
+#[cfg(feature = "client-blocking")]
+pub use blocking::InfluxWriter;
+#[cfg(feature = "codec")]
+pub use codec::LineProtocolCodec;
+pub use columnar::ColumnarBatch;
+pub use compress::{CompressWriter, CompressedPayload, Compression};
+#[cfg(feature = "serde_json")]
+pub use dead_letter::FileDeadLetter;
+pub use dead_letter::{DeadLetter, DeadLetterEntry, MemoryDeadLetter, Origin};
+#[cfg(feature = "disk-queue")]
+pub use disk_queue::{DiskQueue, DiskQueueConfig, QueuedPayload};
+pub use encoded::EncodedBatch;
+pub use field::{Field, FieldKey, FieldType, FieldValue};
+pub use intern::Interner;
+#[cfg(feature = "testing")]
+pub use lines_eq::{lines_diff, LineSource};
 pub use measurement::Measurement;
 pub use point::{Point, PointBuilder};
 pub use precision::Precision;
+#[cfg(feature = "testing")]
+pub use recording::RecordingSink;
+#[cfg(feature = "http")]
+pub use request::{WriteRequestBuilder, WriteTarget};
+pub use retry::{DropReason, Dropped, RetryBuffer, RetryEntry, RetryPolicy};
+pub use series::Series;
+pub use sink::{Buffer, Filter, Map, PointSink, Tee, WithDefaultTags};
+pub use stats::BatchStats;
+pub use stream::{Connect, StreamConfig, StreamWriter, TcpConnector, TcpWriter};
+#[cfg(unix)]
+pub use stream::{UnixConnector, UnixStreamWriter};
 pub use tag::{Tag, TagKey, TagValue};
+pub use template::PointTemplate;
+#[cfg(feature = "testing")]
+pub use testing::{MockInfluxServer, MockResponse, RecordedRequest};
 pub use timestamp::Timestamp;
+pub use udp::{UdpReport, UdpWriter};
 
 pub type FiledSet = Vec<Field>;
 pub type TagSet = Vec<Tag>;
@@ -0,0 +1,404 @@
+//! Semantic comparison of line protocol behind [`assert_lines_eq!`](crate::assert_lines_eq).
+
+use super::{Batch, EncodedBatch, FieldValue, Point, Precision, RecordingSink, Timestamp};
+use std::{cmp, collections::BTreeMap, fmt::Write};
+
+/// Asserts that two sources of points are equal line by line, ignoring tag order, field order,
+/// float formatting and timestamp precision. Repeated keys are compared as repeated.
+///
+/// Sides are anything implementing [`LineSource`]: line protocol text, points, batches or
+/// [`RecordingSink`]. Text is parsed with nanosecond timestamps unless `precision = ..` is given.
+/// A custom message can follow, also after `precision = ..`. On mismatch the differing
+/// measurements, tags, fields and timestamps are listed per line.
+///
+/// ```
+/// use influxdb_line_protocol::{assert_lines_eq, Point, Precision, Timestamp};
+///
+/// let point = Point::builder("cpu")
+///     .unwrap()
+///     .try_add_tag(("host", "a"))
+///     .try_add_tag(("dc", "eu"))
+///     .try_add_field(("idle", 1.0))
+///     .try_add_field(("busy", 2i64))
+///     .timestamp(Timestamp::Secs(10))
+///     .build()
+///     .unwrap();
+/// assert_lines_eq!(vec![point.clone()], "cpu,host=a,dc=eu busy=2i,idle=1 10000000000");
+/// assert_lines_eq!(point, "cpu,dc=eu,host=a idle=1e0,busy=2i 10", precision = Precision::Secs);
+/// assert_lines_eq!(
+///     point,
+///     "cpu,dc=eu,host=a idle=1,busy=2i 10",
+///     precision = Precision::Secs,
+///     "host {}",
+///     "a"
+/// );
+/// ```
+#[macro_export]
+macro_rules! assert_lines_eq {
+    ($left:expr, $right:expr, precision = $precision:expr, $($arg:tt)+) => {
+        if let Some(diff) = $crate::lines_diff(&$left, &$right, $precision) {
+            panic!(
+                "assertion failed: lines are not equal: {}\n{}",
+                format_args!($($arg)+),
+                diff
+            );
+        }
+    };
+    ($left:expr, $right:expr, precision = $precision:expr $(,)?) => {
+        if let Some(diff) = $crate::lines_diff(&$left, &$right, $precision) {
+            panic!("assertion failed: lines are not equal\n{}", diff);
+        }
+    };
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::assert_lines_eq!($left, $right, precision = $crate::Precision::Nanos)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::assert_lines_eq!($left, $right, precision = $crate::Precision::Nanos, $($arg)+)
+    };
+}
+
+/// Something that yields points to compare with [`lines_diff`].
+pub trait LineSource {
+    /// Points in order. Text is parsed with timestamps in `precision`.
+    fn to_points(&self, precision: Precision) -> Result<Vec<Point>, String>;
+}
+
+impl<T: LineSource + ?Sized> LineSource for &T {
+    fn to_points(&self, precision: Precision) -> Result<Vec<Point>, String> {
+        (**self).to_points(precision)
+    }
+}
+
+/// Empty lines and `#` comments are skipped.
+impl LineSource for str {
+    fn to_points(&self, precision: Precision) -> Result<Vec<Point>, String> {
+        self.lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                Point::parse_line(line, precision)
+                    .map_err(|e| format!("line {} `{}`: {}", i + 1, line, e))
+            })
+            .collect()
+    }
+}
+
+impl LineSource for String {
+    fn to_points(&self, precision: Precision) -> Result<Vec<Point>, String> {
+        self.as_str().to_points(precision)
+    }
+}
+
+impl LineSource for Point {
+    fn to_points(&self, _precision: Precision) -> Result<Vec<Point>, String> {
+        Ok(vec![self.clone()])
+    }
+}
+
+impl LineSource for [Point] {
+    fn to_points(&self, _precision: Precision) -> Result<Vec<Point>, String> {
+        Ok(self.to_vec())
+    }
+}
+
+impl LineSource for Vec<Point> {
+    fn to_points(&self, _precision: Precision) -> Result<Vec<Point>, String> {
+        Ok(self.clone())
+    }
+}
+
+/// Default tags are applied to points.
+impl LineSource for Batch {
+    fn to_points(&self, _precision: Precision) -> Result<Vec<Point>, String> {
+        Ok(self.tagged_points().collect())
+    }
+}
+
+/// Parsed with precision of the batch if it has one.
+impl LineSource for EncodedBatch {
+    fn to_points(&self, precision: Precision) -> Result<Vec<Point>, String> {
+        self.as_str()
+            .to_points(self.precision().unwrap_or(precision))
+    }
+}
+
+impl LineSource for RecordingSink {
+    fn to_points(&self, _precision: Precision) -> Result<Vec<Point>, String> {
+        Ok(self.points())
+    }
+}
+
+/// Describe differences between `left` and `right`, `None` if they are equal.
+///
+/// Points are compared in order. Tags and fields are compared by key, floats by value and
+/// timestamps in nanoseconds. Values of a repeated key are compared in order, so `m v=1,v=2`
+/// differs from `m v=2`. Text sides are parsed with timestamps in `precision`.
+pub fn lines_diff<L, R>(left: &L, right: &R, precision: Precision) -> Option<String>
+where
+    L: LineSource + ?Sized,
+    R: LineSource + ?Sized,
+{
+    let left = match left.to_points(precision) {
+        Ok(points) => points,
+        Err(e) => return Some(format!("left is not valid line protocol: {}", e)),
+    };
+    let right = match right.to_points(precision) {
+        Ok(points) => points,
+        Err(e) => return Some(format!("right is not valid line protocol: {}", e)),
+    };
+
+    let mut out = String::new();
+    if left.len() != right.len() {
+        let _ = writeln!(
+            out,
+            "left has {} lines, right has {}",
+            left.len(),
+            right.len()
+        );
+    }
+    for i in 0..cmp::max(left.len(), right.len()) {
+        match (left.get(i), right.get(i)) {
+            (Some(l), Some(r)) => {
+                let diffs = point_diff(&Normalized::new(l), &Normalized::new(r));
+                if !diffs.is_empty() {
+                    let _ = writeln!(out, "line {}:", i + 1);
+                    let _ = writeln!(out, "  left:  {}", l.to_text_with_precision(None));
+                    let _ = writeln!(out, "  right: {}", r.to_text_with_precision(None));
+                    for diff in diffs {
+                        let _ = writeln!(out, "  {}", diff);
+                    }
+                }
+            }
+            (Some(l), None) => {
+                let _ = writeln!(
+                    out,
+                    "line {}: only left: {}",
+                    i + 1,
+                    l.to_text_with_precision(None)
+                );
+            }
+            (None, Some(r)) => {
+                let _ = writeln!(
+                    out,
+                    "line {}: only right: {}",
+                    i + 1,
+                    r.to_text_with_precision(None)
+                );
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    if out.is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+// point with order independent tags and fields, and timestamp in nanoseconds
+struct Normalized<'a> {
+    measurement: &'a str,
+    // all values of repeated key in original order
+    tags: BTreeMap<&'a str, Vec<&'a str>>,
+    fields: BTreeMap<&'a str, Vec<&'a FieldValue>>,
+    timestamp: Timestamp,
+}
+
+impl<'a> Normalized<'a> {
+    fn new(point: &'a Point) -> Self {
+        let timestamp = point.timestamp();
+        let mut tags = BTreeMap::<_, Vec<_>>::new();
+        for tag in point.tags() {
+            tags.entry(tag.key().as_ref())
+                .or_default()
+                .push(tag.value().as_ref());
+        }
+        let mut fields = BTreeMap::<_, Vec<_>>::new();
+        for field in point.fields() {
+            fields
+                .entry(field.key().as_ref())
+                .or_default()
+                .push(field.value());
+        }
+        Self {
+            measurement: point.measurement().as_ref(),
+            tags,
+            fields,
+            // keep original on overflow
+            timestamp: timestamp
+                .timestamp_nanos()
+                .map(Timestamp::Nanos)
+                .unwrap_or(timestamp),
+        }
+    }
+}
+
+fn point_diff(left: &Normalized<'_>, right: &Normalized<'_>) -> Vec<String> {
+    let mut diffs = vec![];
+    if left.measurement != right.measurement {
+        diffs.push(format!(
+            "measurement: `{}` != `{}`",
+            left.measurement, right.measurement
+        ));
+    }
+    for key in union(&left.tags, &right.tags) {
+        let (l, r) = (left.tags.get(key), right.tags.get(key));
+        if l != r {
+            diffs.push(format!(
+                "tag `{}`: {} != {}",
+                key,
+                show(l.map(|v| join(v, |v| format!("`{}`", v)))),
+                show(r.map(|v| join(v, |v| format!("`{}`", v))))
+            ));
+        }
+    }
+    for key in union(&left.fields, &right.fields) {
+        let (l, r) = (left.fields.get(key), right.fields.get(key));
+        if l != r {
+            diffs.push(format!(
+                "field `{}`: {} != {}",
+                key,
+                show(l.map(|v| join(v, |v| field_text(v)))),
+                show(r.map(|v| join(v, |v| field_text(v))))
+            ));
+        }
+    }
+    if left.timestamp != right.timestamp {
+        diffs.push(format!(
+            "timestamp: {} != {}",
+            timestamp_text(left.timestamp),
+            timestamp_text(right.timestamp)
+        ));
+    }
+    diffs
+}
+
+fn union<'a, V>(left: &BTreeMap<&'a str, V>, right: &BTreeMap<&'a str, V>) -> Vec<&'a str> {
+    let mut keys = left.keys().chain(right.keys()).copied().collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+    keys
+}
+
+fn join<T>(values: &[T], text: impl Fn(&T) -> String) -> String {
+    values.iter().map(text).collect::<Vec<_>>().join(", ")
+}
+
+fn show(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "(missing)".to_string())
+}
+
+fn field_text(value: &FieldValue) -> String {
+    let mut out = String::new();
+    value.write_text(&mut out);
+    out
+}
+
+fn timestamp_text(timestamp: Timestamp) -> String {
+    match timestamp {
+        Timestamp::Now => "(none)".to_string(),
+        Timestamp::Nanos(v) => format!("{}ns", v),
+        Timestamp::Micro(v) => format!("{}us", v),
+        Timestamp::Milli(v) => format!("{}ms", v),
+        Timestamp::Secs(v) => format!("{}s", v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PointSink, Tag};
+    use std::convert::TryFrom;
+
+    fn point(v: i64) -> Point {
+        Point::builder("m")
+            .unwrap()
+            .try_add_field(("v", v))
+            .try_add_field(("f", 0.5))
+            .timestamp(Timestamp::Milli(v))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn semantic_equality() {
+        let batch = Batch::from(vec![point(1), point(2)])
+            .with_default_tags(vec![Tag::try_from(("host", "a")).unwrap()]);
+        assert_lines_eq!(
+            batch,
+            "m,host=a f=5e-1,v=1i 1000000\n\n# comment\nm,host=a v=2i,f=0.50 2000000"
+        );
+        assert_lines_eq!(
+            batch.to_line_protocol_lossy(None),
+            batch,
+            precision = Precision::Milli
+        );
+
+        let mut sink = RecordingSink::new();
+        sink.clone().send_batch(&batch).unwrap();
+        assert_lines_eq!(sink, batch, "recorded {} points", sink.len());
+        sink.flush().unwrap();
+        assert_eq!(sink.flushes(), 1);
+        assert_eq!(sink.take().len(), 2);
+        assert!(sink.is_empty());
+    }
+
+    #[test]
+    fn readable_diff() {
+        let left = "cpu,host=a,dc=eu idle=1,busy=2i 10\ncpu idle=3";
+        let right = "mem,host=b busy=2u,user=true 20";
+        let diff = lines_diff(left, right, Precision::Secs).unwrap();
+        assert_eq!(
+            diff,
+            "left has 2 lines, right has 1\n\
+             line 1:\n  \
+             left:  cpu,dc=eu,host=a idle=1.0,busy=2i 10\n  \
+             right: mem,host=b busy=2u,user=true 20\n  \
+             measurement: `cpu` != `mem`\n  \
+             tag `dc`: `eu` != (missing)\n  \
+             tag `host`: `a` != `b`\n  \
+             field `busy`: 2i != 2u\n  \
+             field `idle`: 1.0 != (missing)\n  \
+             field `user`: (missing) != true\n  \
+             timestamp: 10000000000ns != 20000000000ns\n\
+             line 2: only left: cpu idle=3.0\n"
+        );
+        assert_eq!(
+            lines_diff("m,t=a,t=b v=1,v=2", "m,t=b v=2", Precision::Nanos).unwrap(),
+            "line 1:\n  \
+             left:  m,t=a,t=b v=1.0,v=2.0\n  \
+             right: m,t=b v=2.0\n  \
+             tag `t`: `a`, `b` != `b`\n  \
+             field `v`: 1.0, 2.0 != 2.0\n"
+        );
+        assert!(lines_diff("m v=1", "m v=", Precision::Nanos)
+            .unwrap()
+            .starts_with("right is not valid line protocol: line 1 `m v=`"));
+    }
+
+    #[test]
+    #[should_panic(expected = "field `v`: 1i != 2i")]
+    fn assert_panics() {
+        assert_lines_eq!(point(1), "m v=2i,f=0.5 1", precision = Precision::Milli);
+    }
+
+    #[test]
+    #[should_panic(expected = "lines are not equal: point 1\nline 1:")]
+    fn assert_panics_with_precision_and_message() {
+        assert_lines_eq!(
+            point(1),
+            point(1),
+            precision = Precision::Milli,
+            "point {}",
+            1
+        );
+        assert_lines_eq!(
+            point(1),
+            "m v=1i,f=0.5 2",
+            precision = Precision::Milli,
+            "point {}",
+            1
+        );
+    }
+}
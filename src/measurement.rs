@@ -1,16 +1,17 @@
 use super::{error::Error, name_restriction::check_measurement};
 use derive_more::{Deref, Display};
-use std::{borrow::Borrow, convert::TryFrom};
+use std::{borrow::Borrow, convert::TryFrom, sync::Arc};
 
 ///The part of the InfluxDB data structure that describes the data stored in the associated fields.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash, Display, Deref)]
-pub struct Measurement(String);
+#[deref(forward)]
+pub struct Measurement(Arc<str>);
 
 impl Measurement {
     pub fn new(measurement: impl Into<String>) -> Result<Self, Error> {
         let measurement = measurement.into();
         check_measurement(&measurement)?;
-        Ok(Measurement(measurement))
+        Ok(Measurement(measurement.into()))
     }
 }
 
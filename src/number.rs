@@ -0,0 +1,105 @@
+//! Numbers formatting on stack buffers (without allocation).
+//!
+//! Floats are written in the shortest form that parses back to the identical `f64`.
+
+#[inline]
+pub fn push_integer<I: itoa::Integer>(out: &mut String, v: I) {
+    out.push_str(itoa::Buffer::new().format(v))
+}
+
+#[inline]
+pub fn integer_len<I: itoa::Integer>(v: I) -> usize {
+    itoa::Buffer::new().format(v).len()
+}
+
+#[inline]
+pub fn push_float(out: &mut String, v: f64) {
+    out.push_str(ryu::Buffer::new().format(v))
+}
+
+#[inline]
+pub fn float_len(v: f64) -> usize {
+    ryu::Buffer::new().format(v).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FieldValue;
+    use std::convert::TryFrom;
+
+    fn assert_round_trip(v: f64) {
+        let text = FieldValue::try_from(v).unwrap().to_text();
+        let parsed = text.parse::<f64>().unwrap();
+        assert_eq!(parsed.to_bits(), v.to_bits(), "{} encoded as {}", v, text);
+        assert_eq!(float_len(v), text.len());
+    }
+
+    #[test]
+    fn float_round_trip_edge_cases() {
+        let cases = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.0,
+            0.1,
+            0.3,
+            1.0 / 3.0,
+            64.4,
+            1e21,
+            1e-7,
+            123_456_789_012_345_680.0,
+            f64::EPSILON,
+            f64::MIN_POSITIVE,
+            f64::from_bits(1),                     // smallest subnormal
+            f64::from_bits(0x000F_FFFF_FFFF_FFFF), // biggest subnormal
+            -f64::from_bits(1),
+            f64::MAX,
+            f64::MIN,
+            9_007_199_254_740_993.0, // 2^53 + 1
+        ];
+        for v in cases.iter() {
+            assert_round_trip(*v);
+        }
+    }
+
+    #[test]
+    fn float_round_trip_bit_patterns() {
+        // xorshift to cover all exponents without extra dependency
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for _ in 0..100_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let v = f64::from_bits(state);
+            if v.is_finite() {
+                assert_round_trip(v);
+            }
+        }
+    }
+
+    #[test]
+    fn reject_non_finite() {
+        for v in [f64::INFINITY, f64::NEG_INFINITY].iter() {
+            assert!(matches!(
+                FieldValue::try_from(*v),
+                Err(crate::error::Error::InfiniteFloat)
+            ));
+        }
+        assert!(FieldValue::try_from(f32::INFINITY).is_err());
+        assert!(FieldValue::try_from(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn integers() {
+        for v in [0, -1, 1, i64::MIN, i64::MAX].iter() {
+            let mut out = String::new();
+            push_integer(&mut out, *v);
+            assert_eq!(out, v.to_string());
+            assert_eq!(integer_len(*v), out.len());
+        }
+        let mut out = String::new();
+        push_integer(&mut out, u64::MAX);
+        assert_eq!(out, u64::MAX.to_string());
+    }
+}
@@ -0,0 +1,244 @@
+//! Parser of line protocol, the reverse of [`Point`] encoding.
+//!
+//! [External
+//! doc](https://v2.docs.influxdata.com/v2.0/reference/syntax/line-protocol/)
+
+use super::{
+    error::Error, Field, FieldKey, FieldValue, Measurement, Point, Precision, Tag, TagKey,
+    TagValue, Timestamp,
+};
+use std::{
+    convert::{TryFrom, TryInto},
+    str::FromStr,
+};
+
+impl Point {
+    /// Parse one line of line protocol. Timestamp is read in `precision`.
+    pub fn parse_line(line: &str, precision: Precision) -> Result<Self, Error> {
+        Parser { line, pos: 0 }.point(precision)
+    }
+}
+
+/// Parse line with timestamp in nanoseconds.
+impl FromStr for Point {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Point::parse_line(s, Precision::Nanos)
+    }
+}
+
+struct Parser<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn point(mut self, precision: Precision) -> Result<Point, Error> {
+        if self.line.contains('\n') {
+            return Err(Error::NewLine);
+        }
+        let measurement = self.unescape_until(b", ", b", ");
+        if measurement.is_empty() {
+            return Err(parse_error("missing measurement"));
+        }
+        let measurement = Measurement::new(measurement)?;
+
+        let mut tags = vec![];
+        while self.next_if(b',') {
+            let key = self.unescape_until(b"=, ", b"=, ");
+            if !self.next_if(b'=') || key.is_empty() {
+                return Err(parse_error("missing tag key"));
+            }
+            let value = self.unescape_until(b", ", b"=, ");
+            if value.is_empty() {
+                return Err(parse_error("missing tag value"));
+            }
+            tags.push(Tag::from_parts(
+                TagKey::try_from(key)?,
+                TagValue::try_from(value)?,
+            ));
+        }
+        tags.sort();
+
+        if !self.next_if(b' ') {
+            return Err(Error::NoField);
+        }
+        let mut fields = vec![];
+        loop {
+            let key = self.unescape_until(b"=, ", b"=, ");
+            if !self.next_if(b'=') || key.is_empty() {
+                return Err(parse_error("missing field key"));
+            }
+            let value = self.field_value()?;
+            fields.push(Field::from_parts(FieldKey::new(key)?, value));
+            if !self.next_if(b',') {
+                break;
+            }
+        }
+
+        let timestamp = if self.next_if(b' ') {
+            let rest = &self.line[self.pos..];
+            let value = rest
+                .parse::<i64>()
+                .map_err(|_| parse_error(format!("invalid timestamp `{}`", rest)))?;
+            Timestamp::from_precision(Some(precision), value)
+        } else if self.pos == self.line.len() {
+            Timestamp::Now
+        } else {
+            return Err(parse_error("unexpected character after fields"));
+        };
+        Ok(Point::from_parts(measurement, tags, fields, timestamp))
+    }
+
+    fn field_value(&mut self) -> Result<FieldValue, Error> {
+        if self.next_if(b'"') {
+            let value = self.unescape_until(b"\"", b"\"\\");
+            if !self.next_if(b'"') {
+                return Err(parse_error("unterminated string field value"));
+            }
+            return value.try_into();
+        }
+        let start = self.pos;
+        let len = self.line[start..]
+            .find([',', ' '])
+            .unwrap_or(self.line.len() - start);
+        self.pos += len;
+        let text = &self.line[start..self.pos];
+        let invalid = || parse_error(format!("invalid field value `{}`", text));
+        match text {
+            "t" | "T" | "true" | "True" | "TRUE" => Ok(FieldValue::Boolean(true)),
+            "f" | "F" | "false" | "False" | "FALSE" => Ok(FieldValue::Boolean(false)),
+            _ if text.ends_with('i') => text[..text.len() - 1]
+                .parse::<i64>()
+                .map(FieldValue::Integer)
+                .map_err(|_| invalid()),
+            _ if text.ends_with('u') => text[..text.len() - 1]
+                .parse::<u64>()
+                .map(FieldValue::UInteger)
+                .map_err(|_| invalid()),
+            // `parse` accepts `inf` and `nan`, InfluxDB doesn't
+            _ if text.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) => {
+                let value = text.parse::<f64>().map_err(|_| invalid())?;
+                if value.is_finite() {
+                    value.try_into()
+                } else {
+                    Err(invalid())
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Consume text up to first unescaped `stop` byte. Backslash is removed only before
+    /// `escaped` bytes.
+    fn unescape_until(&mut self, stop: &[u8], escaped: &[u8]) -> String {
+        let bytes = self.line.as_bytes();
+        let mut out = String::new();
+        let mut start = self.pos;
+        let mut i = self.pos;
+        while i < bytes.len() && !stop.contains(&bytes[i]) {
+            if bytes[i] == b'\\' && i + 1 < bytes.len() && escaped.contains(&bytes[i + 1]) {
+                out.push_str(&self.line[start..i]);
+                out.push(char::from(bytes[i + 1]));
+                i += 2;
+                start = i;
+            } else {
+                i += 1;
+            }
+        }
+        out.push_str(&self.line[start..i]);
+        self.pos = i;
+        out
+    }
+
+    fn next_if(&mut self, byte: u8) -> bool {
+        if self.line.as_bytes().get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn parse_error(message: impl Into<String>) -> Error {
+    Error::Parse(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line() {
+        let point = Point::parse_line(
+            r#"my\ meas\,ure,b=2,a\=x=1\ 2 s="quote \" and \\ back",i=-3i,u=4u,f=1.5,b=T 17"#,
+            Precision::Secs,
+        )
+        .unwrap();
+        assert_eq!(point.measurement().as_ref(), "my meas,ure");
+        let tags = point
+            .tags()
+            .iter()
+            .map(|t| (t.key().as_ref(), t.value().as_ref()))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec![("a=x", "1 2"), ("b", "2")]);
+        let fields = point
+            .fields()
+            .iter()
+            .map(|f| f.value().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                FieldValue::String(r#"quote " and \ back"#.into()),
+                FieldValue::Integer(-3),
+                FieldValue::UInteger(4),
+                FieldValue::try_from(1.5).unwrap(),
+                FieldValue::Boolean(true),
+            ]
+        );
+        assert_eq!(point.timestamp(), Timestamp::Secs(17));
+
+        let point: Point = "m v=1".parse().unwrap();
+        assert_eq!(point.timestamp(), Timestamp::Now);
+        assert_eq!(point.to_text_with_precision(None), "m v=1.0");
+    }
+
+    #[test]
+    fn round_trip() {
+        let point = Point::builder("w e,ird")
+            .unwrap()
+            .try_add_tag(("k =,", "v =,\\x"))
+            .try_add_field(("s", r#"a "b" \c"#))
+            .try_add_field(("x y", 2u64))
+            .timestamp(Timestamp::Nanos(-5))
+            .build()
+            .unwrap();
+        let line = point.to_text_with_precision(None);
+        assert_eq!(line.parse::<Point>().unwrap(), point);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        for line in &[
+            "",
+            "m",
+            "m ",
+            ",t=1 v=1",
+            "m,t v=1",
+            "m,t= v=1",
+            "m v=",
+            "m v=\"open",
+            "m v=1x",
+            "m v=nan",
+            "m v=1 ts",
+            "m v=1  1",
+            "m v=1i\n",
+            "m _v=1",
+        ] {
+            assert!(line.parse::<Point>().is_err(), "{:?} parsed", line);
+        }
+        assert!(matches!("m".parse::<Point>(), Err(Error::NoField)));
+    }
+}
@@ -1,5 +1,10 @@
-use super::{error::Error, escape, Field, Measurement, Precision, Tag, TagSet, Timestamp};
-use std::{convert::TryInto, iter::FromIterator};
+use super::{
+    batch::FieldConflict,
+    dead_letter::{DeadLetter, DeadLetterEntry, Origin},
+    error::Error,
+    escape, number, Field, Measurement, Precision, Series, Tag, TagSet, TagValue, Timestamp,
+};
+use std::{convert::TryInto, io, iter::FromIterator};
 
 /// Represents a single data record
 ///
@@ -19,42 +24,183 @@ impl Point {
         PointBuilder::new(measurment)
     }
 
+    pub(crate) fn from_parts(
+        measurment: Measurement,
+        tag_set: TagSet,
+        field_set: Vec<Field>,
+        timestamp: Timestamp,
+    ) -> Self {
+        Self {
+            measurment,
+            tag_set,
+            field_set,
+            timestamp,
+        }
+    }
+
+    pub(crate) fn into_parts(self) -> (Measurement, TagSet, Vec<Field>) {
+        (self.measurment, self.tag_set, self.field_set)
+    }
+
     pub fn precision(&self) -> Option<Precision> {
         self.timestamp.precision()
     }
 
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    pub fn measurement(&self) -> &Measurement {
+        &self.measurment
+    }
+
+    /// Tags of this point sorted by key.
+    pub fn tags(&self) -> &[Tag] {
+        &self.tag_set
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.field_set
+    }
+
+    /// Get tag value for given tag key.
+    pub fn tag_value(&self, key: &str) -> Option<&TagValue> {
+        self.tag_set
+            .iter()
+            .find(|t| AsRef::<str>::as_ref(t.key()) == key)
+            .map(|t| t.value())
+    }
+
+    /// Series this point belongs to.
+    pub fn series(&self) -> Series {
+        Series::new(self.measurment.clone(), self.tag_set.clone())
+    }
+
+    /// Series of this point with `default_tags` not overwritten by point, as it is written.
+    pub(crate) fn series_with_default_tags(&self, default_tags: &[Tag]) -> Series {
+        Series::new(
+            self.measurment.clone(),
+            self.merged_tags(default_tags).cloned().collect(),
+        )
+    }
+
+    /// Length in bytes of `to_text_with_default_tags` output, computed without building it.
+    pub(crate) fn encoded_len(&self, precision: Option<Precision>, default_tags: &[Tag]) -> usize {
+        let tags_len = self
+            .merged_tags(default_tags)
+            .map(|t| 1 + t.encoded_len())
+            .sum::<usize>();
+        let fields_len = self
+            .field_set
+            .iter()
+            .map(|f| 1 + f.encoded_len())
+            .sum::<usize>();
+        let ts_len = match self.timestamp_with_precision(precision) {
+            Timestamp::Now => 0,
+            Timestamp::Nanos(v)
+            | Timestamp::Micro(v)
+            | Timestamp::Milli(v)
+            | Timestamp::Secs(v) => 1 + number::integer_len(v),
+        };
+        escape::measurement_len(&self.measurment) + tags_len + fields_len + ts_len
+    }
+
+    // point tags with `default_tags` that are not overwritten by point, sorted
+    fn merged_tags<'a>(&'a self, default_tags: &'a [Tag]) -> impl Iterator<Item = &'a Tag> {
+        let mut tags = self.tag_set.iter().collect::<Vec<_>>();
+        if !default_tags.is_empty() {
+            tags.extend(
+                default_tags
+                    .iter()
+                    .filter(|d| self.tag_set.iter().all(|t| t.key() != d.key())),
+            );
+            tags.sort();
+        }
+        tags.into_iter()
+    }
+
+    fn timestamp_with_precision(&self, precision: Option<Precision>) -> Timestamp {
+        precision
+            .map(|p| self.timestamp.timestamp_precision_lossy(p))
+            .unwrap_or(self.timestamp)
+    }
+
+    /// Add `default_tags` with keys not already present in tag set.
+    pub(crate) fn add_default_tags(&mut self, default_tags: &[Tag]) {
+        if default_tags.is_empty() {
+            return;
+        }
+        for tag in default_tags {
+            if self.tag_set.iter().all(|t| t.key() != tag.key()) {
+                self.tag_set.push(tag.clone());
+            }
+        }
+        self.tag_set.sort();
+    }
+
+    /// Move fields of `other` into `self` resolving duplicated field keys with `conflict`.
+    pub(crate) fn merge_fields(&mut self, other: Point, conflict: FieldConflict) {
+        for field in other.field_set {
+            match self.field_set.iter_mut().find(|f| f.key() == field.key()) {
+                Some(existing) => match conflict {
+                    FieldConflict::KeepFirst => {}
+                    FieldConflict::KeepLast => *existing = field,
+                },
+                None => self.field_set.push(field),
+            }
+        }
+    }
+
     pub(crate) fn to_text_with_precision(&self, precision: Option<Precision>) -> String {
-        let mut line = escape::measurement(&self.measurment);
-        for tag_set in &self.tag_set {
-            line += &format!(",{}", tag_set.to_text());
+        self.to_text_with_default_tags(precision, &[])
+    }
+
+    /// Same as `to_text_with_precision` but `default_tags` are added to tag set. Point tags
+    /// have priority over `default_tags` with the same key.
+    pub(crate) fn to_text_with_default_tags(
+        &self,
+        precision: Option<Precision>,
+        default_tags: &[Tag],
+    ) -> String {
+        let mut line = String::with_capacity(self.encoded_len(precision, default_tags));
+        self.write_text(&mut line, precision, default_tags);
+        line
+    }
+
+    /// Append line protocol representation of this point to `out`.
+    pub(crate) fn write_text(
+        &self,
+        out: &mut String,
+        precision: Option<Precision>,
+        default_tags: &[Tag],
+    ) {
+        *out += &escape::measurement(&self.measurment);
+        for tag_set in self.merged_tags(default_tags) {
+            out.push(',');
+            *out += &tag_set.to_text();
         }
 
         let mut first_iter = true;
         for field_set in &self.field_set {
             if first_iter {
                 first_iter = false;
-                line += &format!(" {}", field_set.to_text());
+                out.push(' ');
             } else {
-                line += &format!(",{}", field_set.to_text());
+                out.push(',');
             }
+            field_set.write_text(out);
         }
 
-        let ts = precision
-            .map(|p| self.timestamp.timestamp_precision_lossy(p))
-            .unwrap_or(self.timestamp);
-
-        match ts {
+        match self.timestamp_with_precision(precision) {
             Timestamp::Now => {}
             Timestamp::Nanos(v)
             | Timestamp::Micro(v)
             | Timestamp::Milli(v)
             | Timestamp::Secs(v) => {
-                line += " ";
-                line += &v.to_string();
+                out.push(' ');
+                number::push_integer(out, v);
             }
         }
-
-        line
     }
 }
 
@@ -70,6 +216,11 @@ pub struct PointBuilder {
 impl PointBuilder {
     pub fn new(measurment: impl Into<String>) -> Result<Self, Error> {
         let measurment = Measurement::new(measurment)?;
+        Ok(Self::with_measurement(measurment))
+    }
+
+    /// Create builder from already validated (for example interned) measurement.
+    pub fn with_measurement(measurment: Measurement) -> Self {
         let point = Point {
             measurment,
             tag_set: Default::default(),
@@ -77,10 +228,10 @@ impl PointBuilder {
             timestamp: Timestamp::Now,
         };
 
-        Ok(Self {
+        Self {
             point,
             errors: vec![],
-        })
+        }
     }
 
     pub fn add_tag(mut self, tag_set: impl Into<Tag>) -> Self {
@@ -209,6 +360,23 @@ impl PointBuilder {
             Ok(self.point)
         }
     }
+
+    /// Like [`build`](PointBuilder::build), but invalid point is sent to `dead_letter` with
+    /// its valid part as line. Point without fields is sent with [`Error::NoField`] instead of
+    /// panicking.
+    pub fn build_or_dead_letter(
+        self,
+        dead_letter: &mut impl DeadLetter,
+    ) -> io::Result<Option<Point>> {
+        let error = match self.errors.first() {
+            Some(error) => error.clone(),
+            None if self.point.field_set.is_empty() => Error::NoField,
+            None => return Ok(self.build().ok()),
+        };
+        let line = self.point.to_text_with_precision(None);
+        dead_letter.send(DeadLetterEntry::new(line, error, Origin::Validation))?;
+        Ok(None)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,63 @@
+//! Sink keeping emitted points in memory for assertions.
+
+use super::{sync, Point};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// [`PointSink`](crate::PointSink) recording every point sent to it. Clones share the same
+/// records, so one clone can be given to the tested component and the other inspected.
+///
+/// Default tags of sent batches are applied to recorded points.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingSink {
+    inner: Arc<Mutex<Records>>,
+}
+
+#[derive(Debug, Default)]
+struct Records {
+    points: Vec<Point>,
+    flushes: usize,
+}
+
+impl RecordingSink {
+    /// Sink with no records.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Recorded points in order they were sent.
+    pub fn points(&self) -> Vec<Point> {
+        self.lock().points.clone()
+    }
+
+    /// Take recorded points, leaving none.
+    pub fn take(&self) -> Vec<Point> {
+        std::mem::take(&mut self.lock().points)
+    }
+
+    /// Number of recorded points.
+    pub fn len(&self) -> usize {
+        self.lock().points.len()
+    }
+
+    /// Whether no point is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.lock().points.is_empty()
+    }
+
+    /// Number of [`flush`](crate::PointSink::flush) calls.
+    pub fn flushes(&self) -> usize {
+        self.lock().flushes
+    }
+
+    pub(crate) fn record(&self, points: impl IntoIterator<Item = Point>) {
+        self.lock().points.extend(points);
+    }
+
+    pub(crate) fn record_flush(&self) {
+        self.lock().flushes += 1;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Records> {
+        sync::lock(&self.inner)
+    }
+}
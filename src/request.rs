@@ -0,0 +1,188 @@
+//! Transport agnostic requests for [InfluxDB v2 write API](https://v2.docs.influxdata.com/v2.0/api/#operation/PostWrite).
+
+use super::{error::WriteError, Batch, CompressedPayload, Compression, Precision};
+use http1::{header, Method, Request};
+
+/// Where and how points are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteTarget {
+    /// Address of InfluxDB server, for example `http://localhost:8086`.
+    pub base_url: String,
+    pub org: String,
+    pub bucket: String,
+    /// Precision of timestamps. If `None` the highest precision of points in batch is used.
+    pub precision: Option<Precision>,
+    /// Token sent in `Authorization: Token` header.
+    pub token: Option<String>,
+}
+
+impl WriteTarget {
+    pub fn new(
+        base_url: impl Into<String>,
+        org: impl Into<String>,
+        bucket: impl Into<String>,
+    ) -> Self {
+        Self {
+            base_url: base_url.into(),
+            org: org.into(),
+            bucket: bucket.into(),
+            precision: None,
+            token: None,
+        }
+    }
+
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Full url of write endpoint with query parameters.
+    pub fn write_url(&self, precision: Precision) -> String {
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("org", &self.org)
+            .append_pair("bucket", &self.bucket)
+            .append_pair("precision", &precision.to_string())
+            .finish();
+        format!(
+            "{}/api/v2/write?{}",
+            self.base_url.trim_end_matches('/'),
+            query
+        )
+    }
+}
+
+/// Builder of [`http::Request`](http1::Request) writing [`Batch`] to [`WriteTarget`].
+///
+/// Request can be sent with any HTTP client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteRequestBuilder {
+    target: WriteTarget,
+    compression: Compression,
+}
+
+impl WriteRequestBuilder {
+    pub fn new(target: WriteTarget) -> Self {
+        Self {
+            target,
+            compression: Compression::None,
+        }
+    }
+
+    /// Compress request body. InfluxDB accepts only `gzip` content encoding.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn target(&self) -> &WriteTarget {
+        &self.target
+    }
+
+    /// Encode and compress `batch` the same way as [`build`](WriteRequestBuilder::build) does.
+    ///
+    /// Returns payload with precision of its timestamps.
+    pub fn encode(&self, batch: &Batch) -> Result<(CompressedPayload, Precision), WriteError> {
+        let precision = self
+            .target
+            .precision
+            .or_else(|| batch.precision())
+            .unwrap_or_default();
+        let payload = batch.to_compressed(self.compression, Some(precision))?;
+        Ok((payload, precision))
+    }
+
+    /// Build write request for `batch`.
+    pub fn build(&self, batch: &Batch) -> Result<Request<Vec<u8>>, WriteError> {
+        let (payload, precision) = self.encode(batch)?;
+        self.build_payload(payload, precision)
+    }
+
+    /// Build write request for `payload` already encoded with `precision`.
+    pub fn build_payload(
+        &self,
+        payload: CompressedPayload,
+        precision: Precision,
+    ) -> Result<Request<Vec<u8>>, WriteError> {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(self.target.write_url(precision))
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .header(header::ACCEPT, "application/json");
+        if let Some(token) = &self.target.token {
+            request = request.header(header::AUTHORIZATION, format!("Token {}", token));
+        }
+        if let Some(encoding) = payload.content_encoding() {
+            request = request.header(header::CONTENT_ENCODING, encoding);
+        }
+        Ok(request.body(payload.into_bytes())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Point, Timestamp};
+
+    fn batch() -> Batch {
+        Batch::from(vec![
+            Point::builder("cpu")
+                .unwrap()
+                .try_add_field(("a", 1i64))
+                .timestamp(Timestamp::Secs(1))
+                .build()
+                .unwrap(),
+            Point::builder("cpu")
+                .unwrap()
+                .try_add_field(("a", 2i64))
+                .timestamp(Timestamp::Milli(2500))
+                .build()
+                .unwrap(),
+        ])
+    }
+
+    #[test]
+    fn build_request() {
+        let target =
+            WriteTarget::new("http://localhost:8086/", "my org", "a&b").with_token("secret");
+        let request = WriteRequestBuilder::new(target).build(&batch()).unwrap();
+
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(
+            request.uri(),
+            "http://localhost:8086/api/v2/write?org=my+org&bucket=a%26b&precision=ms"
+        );
+        assert_eq!(request.headers()[header::AUTHORIZATION], "Token secret");
+        assert_eq!(
+            request.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        assert!(request.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(request.body(), b"cpu a=1i 1000\ncpu a=2i 2500");
+    }
+
+    #[test]
+    fn precision_from_target() {
+        let target = WriteTarget::new("http://localhost:8086", "org", "bucket")
+            .with_precision(Precision::Secs);
+        let request = WriteRequestBuilder::new(target).build(&batch()).unwrap();
+        assert!(request.uri().query().unwrap().ends_with("precision=s"));
+        assert!(request.headers().get(header::AUTHORIZATION).is_none());
+        assert_eq!(request.body(), b"cpu a=1i 1\ncpu a=2i 2");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_request() {
+        let target = WriteTarget::new("http://localhost:8086", "org", "bucket");
+        let request = WriteRequestBuilder::new(target)
+            .compression(Compression::Gzip { level: 1 })
+            .build(&batch())
+            .unwrap();
+        assert_eq!(request.headers()[header::CONTENT_ENCODING], "gzip");
+    }
+}
@@ -0,0 +1,467 @@
+//! Backoff and bounded buffering of failed writes.
+
+use super::{error::WriteError, Batch};
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+/// When and how long writers wait before repeating failed write.
+///
+/// Only errors for which [`WriteError::is_retryable`] is true are repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Wait after first failed attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound of the wait without jitter.
+    pub max_backoff: Duration,
+    /// Each following wait is `multiplier` times longer.
+    pub multiplier: u32,
+    /// Random time up to `jitter` added to every wait.
+    pub jitter: Duration,
+    /// Number of attempts, including the first one, before batch is dropped.
+    pub max_attempts: usize,
+    /// Batch is dropped when it can't be written in this time after first failure.
+    pub max_retry_age: Duration,
+    /// Encoded size of all batches waiting for retry. Oldest batches are dropped to stay below.
+    pub max_buffered_bytes: usize,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            multiplier: 2,
+            jitter: Duration::from_millis(500),
+            max_attempts: 5,
+            max_retry_age: Duration::from_secs(300),
+            max_buffered_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never repeats a write.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Wait without jitter after `attempt` (counted from 1) failed.
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(u32::MAX as usize) as u32;
+        self.multiplier
+            .checked_pow(exponent)
+            .and_then(|factor| self.initial_backoff.checked_mul(factor))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Wait after `attempt` failed with `error`: backoff with jitter, but not shorter than
+    /// `Retry-After` sent by server.
+    pub fn delay(&self, attempt: usize, error: &WriteError) -> Duration {
+        let delay = self.backoff(attempt) + random_duration(self.jitter);
+        error.retry_after().map_or(delay, |wait| wait.max(delay))
+    }
+
+    fn min_delay(&self, attempt: usize, error: &WriteError) -> Duration {
+        let delay = self.backoff(attempt);
+        error.retry_after().map_or(delay, |wait| wait.max(delay))
+    }
+
+    /// Why write that failed `attempts` times with `error`, first failure `age` ago, must not
+    /// be repeated. `None` means it may be repeated after [`delay`](RetryPolicy::delay).
+    pub fn drop_reason(
+        &self,
+        attempts: usize,
+        age: Duration,
+        error: &WriteError,
+    ) -> Option<DropReason> {
+        if !error.is_retryable() {
+            Some(DropReason::NotRetryable)
+        } else if attempts >= self.max_attempts {
+            Some(DropReason::MaxAttempts)
+        } else if age + self.min_delay(attempts, error) > self.max_retry_age {
+            Some(DropReason::MaxRetryAge)
+        } else {
+            None
+        }
+    }
+}
+
+/// Why failed [`Batch`] was given up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    NotRetryable,
+    MaxAttempts,
+    MaxRetryAge,
+    /// Batch did not fit into [`RetryPolicy::max_buffered_bytes`].
+    BufferFull,
+    /// Writer was shut down before batch could be written.
+    Shutdown,
+}
+
+/// Batch that will not be written again.
+#[derive(Debug, Clone)]
+pub struct Dropped {
+    pub batch: Batch,
+    /// Error of the last attempt.
+    pub error: WriteError,
+    pub reason: DropReason,
+}
+
+/// Failed batch waiting in [`RetryBuffer`].
+#[derive(Debug, Clone)]
+pub struct RetryEntry {
+    pub(crate) batch: Batch,
+    bytes: usize,
+    attempts: usize,
+    last_error: WriteError,
+    first_failure: Instant,
+    next_attempt: Instant,
+}
+
+impl RetryEntry {
+    pub fn batch(&self) -> &Batch {
+        &self.batch
+    }
+
+    pub fn into_batch(self) -> Batch {
+        self.batch
+    }
+
+    /// Number of failed attempts.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// Error of the last failed attempt.
+    pub fn last_error(&self) -> &WriteError {
+        &self.last_error
+    }
+
+    pub fn first_failure(&self) -> Instant {
+        self.first_failure
+    }
+
+    pub fn next_attempt(&self) -> Instant {
+        self.next_attempt
+    }
+}
+
+/// Failed batches waiting for next attempt, bounded by [`RetryPolicy`].
+///
+/// Time is passed by caller, so buffer works with any clock.
+#[derive(Debug, Clone)]
+pub struct RetryBuffer {
+    policy: RetryPolicy,
+    entries: VecDeque<RetryEntry>,
+    bytes: usize,
+}
+
+impl RetryBuffer {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            entries: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Keep `batch` whose first write failed with `error` at `now`.
+    ///
+    /// Returns batches that are given up, including `batch` itself if it can't be repeated.
+    pub fn push(&mut self, batch: Batch, error: WriteError, now: Instant) -> Vec<Dropped> {
+        let entry = RetryEntry {
+            bytes: batch.encoded_len(),
+            batch,
+            attempts: 0,
+            last_error: error,
+            first_failure: now,
+            next_attempt: now,
+        };
+        self.keep(entry, now)
+    }
+
+    /// Put back `entry` taken by [`pop_due`](RetryBuffer::pop_due) whose attempt failed again.
+    pub fn retry_failed(
+        &mut self,
+        mut entry: RetryEntry,
+        error: WriteError,
+        now: Instant,
+    ) -> Vec<Dropped> {
+        entry.last_error = error;
+        self.keep(entry, now)
+    }
+
+    // count failed attempt of `entry` and keep it if it can be repeated
+    fn keep(&mut self, mut entry: RetryEntry, now: Instant) -> Vec<Dropped> {
+        entry.attempts += 1;
+        let age = now.saturating_duration_since(entry.first_failure);
+        if let Some(reason) = self
+            .policy
+            .drop_reason(entry.attempts, age, &entry.last_error)
+        {
+            return vec![dropped(entry, reason)];
+        }
+        if entry.bytes > self.policy.max_buffered_bytes {
+            return vec![dropped(entry, DropReason::BufferFull)];
+        }
+
+        let mut evicted = vec![];
+        while self.bytes + entry.bytes > self.policy.max_buffered_bytes {
+            // the oldest failure is evicted first
+            let oldest = match self.entries.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            self.bytes -= oldest.bytes;
+            evicted.push(dropped(oldest, DropReason::BufferFull));
+        }
+
+        entry.next_attempt = now + self.policy.delay(entry.attempts, &entry.last_error);
+        self.bytes += entry.bytes;
+        let position = self
+            .entries
+            .iter()
+            .position(|e| e.first_failure > entry.first_failure)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(position, entry);
+        evicted
+    }
+
+    /// Take entry whose next attempt is due at `now`.
+    pub fn pop_due(&mut self, now: Instant) -> Option<RetryEntry> {
+        let index = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.next_attempt <= now)
+            .min_by_key(|(_, e)| e.next_attempt)
+            .map(|(i, _)| i)?;
+        let entry = self.entries.remove(index)?;
+        self.bytes -= entry.bytes;
+        Some(entry)
+    }
+
+    /// The earliest time one of entries should be written again.
+    pub fn next_attempt(&self) -> Option<Instant> {
+        self.entries.iter().map(|e| e.next_attempt).min()
+    }
+
+    /// Take all entries, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = RetryEntry> + '_ {
+        self.bytes = 0;
+        self.entries.drain(..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encoded size of all waiting batches.
+    pub fn buffered_bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+fn dropped(entry: RetryEntry, reason: DropReason) -> Dropped {
+    Dropped {
+        batch: entry.batch,
+        error: entry.last_error,
+        reason,
+    }
+}
+
+/// Random duration shorter than `max`.
+pub(crate) fn random_duration(max: Duration) -> Duration {
+    let max_nanos = max.as_nanos() as u64;
+    if max_nanos == 0 {
+        return Duration::from_nanos(0);
+    }
+    // random seed of `RandomState` is good enough to spread retries
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_nanos(random % max_nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::point;
+
+    fn batch(points: i64) -> Batch {
+        (0..points).map(point).collect()
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 3,
+            jitter: Duration::from_secs(0),
+            max_attempts: 4,
+            max_retry_age: Duration::from_secs(60),
+            max_buffered_bytes: 1000,
+        }
+    }
+
+    fn unavailable() -> WriteError {
+        WriteError::ServiceUnavailable { retry_after: None }
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(unavailable().is_retryable());
+        assert!(WriteError::TooManyRequests { retry_after: None }.is_retryable());
+        assert!(WriteError::Transport("reset".into()).is_retryable());
+        assert!(
+            WriteError::from(std::io::Error::from(std::io::ErrorKind::TimedOut)).is_retryable()
+        );
+        assert!(WriteError::from_response(502, "", None)
+            .unwrap()
+            .is_retryable());
+        assert!(!WriteError::from_response(400, "bad", None)
+            .unwrap()
+            .is_retryable());
+        assert!(!WriteError::from_response(418, "", None)
+            .unwrap()
+            .is_retryable());
+        assert!(!WriteError::Closed.is_retryable());
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = policy();
+        let delays = (1..6).map(|a| policy.backoff(a)).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [1, 3, 9, 10, 10]
+                .iter()
+                .map(|s| Duration::from_secs(*s))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(policy.backoff(usize::MAX), policy.max_backoff);
+
+        let throttled = WriteError::TooManyRequests {
+            retry_after: Some(Duration::from_secs(30)),
+        };
+        assert_eq!(policy.delay(1, &throttled), Duration::from_secs(30));
+
+        let jittered = RetryPolicy {
+            jitter: Duration::from_millis(100),
+            ..policy
+        };
+        let delay = jittered.delay(1, &unavailable());
+        assert!(delay >= Duration::from_secs(1) && delay < Duration::from_millis(1100));
+    }
+
+    #[test]
+    fn drop_reason() {
+        let policy = policy();
+        let age = Duration::from_secs(0);
+        assert_eq!(policy.drop_reason(1, age, &unavailable()), None);
+        assert_eq!(
+            policy.drop_reason(1, age, &WriteError::Closed),
+            Some(DropReason::NotRetryable)
+        );
+        assert_eq!(
+            policy.drop_reason(4, age, &unavailable()),
+            Some(DropReason::MaxAttempts)
+        );
+        assert_eq!(
+            policy.drop_reason(1, Duration::from_secs(60), &unavailable()),
+            Some(DropReason::MaxRetryAge)
+        );
+        assert_eq!(
+            RetryPolicy::none().drop_reason(1, age, &unavailable()),
+            Some(DropReason::MaxAttempts)
+        );
+    }
+
+    #[test]
+    fn buffer_schedule_and_attempts() {
+        let mut buffer = RetryBuffer::new(policy());
+        let start = Instant::now();
+        assert!(buffer.push(batch(2), unavailable(), start).is_empty());
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.buffered_bytes(), batch(2).encoded_len());
+        assert_eq!(buffer.next_attempt(), Some(start + Duration::from_secs(1)));
+        assert!(buffer.pop_due(start).is_none());
+
+        let mut now = start;
+        for attempt in 1..4 {
+            now += Duration::from_secs(10);
+            let entry = buffer.pop_due(now).unwrap();
+            assert_eq!(entry.attempts(), attempt);
+            assert_eq!(buffer.buffered_bytes(), 0);
+            let dropped = buffer.retry_failed(entry, unavailable(), now);
+            if attempt < 3 {
+                assert!(dropped.is_empty());
+            } else {
+                assert_eq!(dropped.len(), 1);
+                assert_eq!(dropped[0].reason, DropReason::MaxAttempts);
+                assert_eq!(dropped[0].batch.len(), 2);
+            }
+        }
+        assert!(buffer.is_empty());
+
+        let dropped = buffer.push(batch(1), WriteError::Closed, now);
+        assert_eq!(dropped[0].reason, DropReason::NotRetryable);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn buffer_bytes_cap() {
+        let one = batch(10).encoded_len();
+        let mut buffer = RetryBuffer::new(RetryPolicy {
+            max_buffered_bytes: one * 2,
+            ..policy()
+        });
+        let now = Instant::now();
+        assert!(buffer.push(batch(10), unavailable(), now).is_empty());
+        assert!(buffer
+            .push(batch(10), unavailable(), now + Duration::from_secs(1))
+            .is_empty());
+
+        // the oldest batch makes room for the new one and keeps its own error
+        let throttled = WriteError::TooManyRequests { retry_after: None };
+        let dropped = buffer.push(batch(10), throttled, now + Duration::from_secs(2));
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].reason, DropReason::BufferFull);
+        assert!(matches!(
+            dropped[0].error,
+            WriteError::ServiceUnavailable { .. }
+        ));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.buffered_bytes(), one * 2);
+        let entries = buffer.drain().collect::<Vec<_>>();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e.first_failure())
+                .collect::<Vec<_>>(),
+            vec![now + Duration::from_secs(1), now + Duration::from_secs(2)]
+        );
+        assert!(matches!(
+            entries[1].last_error(),
+            WriteError::TooManyRequests { .. }
+        ));
+
+        // batch that alone exceeds the limit is not kept
+        let dropped = buffer.push(batch(30), unavailable(), now);
+        assert_eq!(dropped[0].reason, DropReason::BufferFull);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.buffered_bytes(), 0);
+    }
+}
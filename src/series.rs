@@ -0,0 +1,41 @@
+use super::{escape, Measurement, Tag, TagSet};
+use std::fmt;
+
+/// A logical grouping of data defined by shared measurement and tag set.
+///
+/// Displayed in the same form as series key in line protocol (`measurement,tag1=a,tag2=b`).
+///
+///[external source](https://docs.influxdata.com/influxdb/v1.7/concepts/glossary/#series)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Series {
+    measurement: Measurement,
+    tag_set: TagSet,
+}
+
+impl Series {
+    pub(crate) fn new(measurement: Measurement, mut tag_set: TagSet) -> Self {
+        tag_set.sort();
+        Self {
+            measurement,
+            tag_set,
+        }
+    }
+
+    pub fn measurement(&self) -> &Measurement {
+        &self.measurement
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tag_set
+    }
+}
+
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&escape::measurement(&self.measurement))?;
+        for tag in &self.tag_set {
+            write!(f, ",{}", tag.to_text())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,658 @@
+//! Common interface of everything points can be written to.
+
+use super::{
+    batch::default_tag_set,
+    stream::{Connect, StreamWriter},
+    Batch, EncodedBatch, Point, Tag, TagSet, UdpWriter,
+};
+use std::{convert::Infallible, io};
+
+#[cfg(feature = "disk-queue")]
+use super::DiskQueue;
+#[cfg(feature = "testing")]
+use super::RecordingSink;
+#[cfg(feature = "client-blocking")]
+use super::{error::WriteError, InfluxWriter};
+
+/// Destination of points: transport, file or collection.
+///
+/// `PointSink` is blocking only, there is no async trait. Asynchronous code uses
+/// `futures::Sink<Point>` returned by `AsyncBatchWriter::into_sink` (`async-writer` feature)
+/// with `futures::SinkExt` adapters (`with`, `with_flat_map`, `fanout`, `buffer`) in place of
+/// the combinators below.
+///
+/// Combinators pass batches on with default tags of the batch added to their points, so
+/// points look the same whether they were sent one by one or in a batch.
+///
+/// Producers written against `PointSink` don't change when transport changes, and combinators
+/// add behavior around any sink:
+/// ```
+/// # use influxdb_line_protocol::{Batch, Point, PointSink, Tag};
+/// # use std::convert::TryFrom;
+/// let mut sink = Batch::default()
+///     .filter(|p| p.measurement().as_ref() != "debug")
+///     .with_default_tags(vec![Tag::try_from(("host", "a")).unwrap()]);
+/// let point = Point::builder("cpu").unwrap().try_add_field(("load", 1i64)).build().unwrap();
+/// sink.send(point).unwrap();
+/// assert_eq!(sink.get_ref().get_ref().to_line_protocol_lossy(None), "cpu,host=a load=1i");
+/// ```
+pub trait PointSink {
+    /// Error of the underlying transport or storage. [`Infallible`] for collections.
+    type Error;
+
+    /// Send one point. Sinks may keep it until [`flush`](PointSink::flush).
+    fn send(&mut self, point: Point) -> Result<(), Self::Error>;
+
+    /// Send all points of `batch`. Default implementation sends points one by one with default
+    /// tags of `batch` added.
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        for point in batch.tagged_points() {
+            self.send(point)?;
+        }
+        Ok(())
+    }
+
+    /// Make sure sent points are passed on.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Send only points for which `predicate` returns `true`. Points of batches are checked
+    /// with default tags of the batch added.
+    fn filter<F>(self, predicate: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Point) -> bool,
+    {
+        Filter {
+            sink: self,
+            predicate,
+        }
+    }
+
+    /// Send points changed by `f`. Points of batches are passed to `f` with default tags of
+    /// the batch added.
+    fn map<F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Point) -> Point,
+    {
+        Map { sink: self, f }
+    }
+
+    /// Send points to `other` sink too. See [`Tee`] for use with [`buffer`](PointSink::buffer).
+    fn tee<S>(self, other: S) -> Tee<Self, S>
+    where
+        Self: Sized,
+        S: PointSink<Error = Self::Error>,
+    {
+        Tee { sink: self, other }
+    }
+
+    /// Collect points and send them in batches of `capacity` points.
+    ///
+    /// Points left in buffer are sent on [`flush`](PointSink::flush) only. If sending fails,
+    /// points stay in buffer and are sent again with the next batch.
+    fn buffer(self, capacity: usize) -> Buffer<Self>
+    where
+        Self: Sized,
+    {
+        Buffer {
+            sink: self,
+            batch: Batch::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Add `tags` to every point that has no tag with the same key.
+    fn with_default_tags<I>(self, tags: I) -> WithDefaultTags<Self>
+    where
+        Self: Sized,
+        I: IntoIterator,
+        I::Item: Into<Tag>,
+    {
+        WithDefaultTags {
+            sink: self,
+            tags: default_tag_set(tags),
+        }
+    }
+}
+
+impl<S: PointSink + ?Sized> PointSink for &mut S {
+    type Error = S::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        (**self).send(point)
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        (**self).send_batch(batch)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        (**self).flush()
+    }
+}
+
+impl<S: PointSink + ?Sized> PointSink for Box<S> {
+    type Error = S::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        (**self).send(point)
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        (**self).send_batch(batch)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        (**self).flush()
+    }
+}
+
+macro_rules! adapter_accessors {
+    () => {
+        pub fn get_ref(&self) -> &S {
+            &self.sink
+        }
+
+        pub fn get_mut(&mut self) -> &mut S {
+            &mut self.sink
+        }
+
+        pub fn into_inner(self) -> S {
+            self.sink
+        }
+    };
+}
+
+/// Sink returned by [`PointSink::filter`].
+#[derive(Debug, Clone)]
+pub struct Filter<S, F> {
+    sink: S,
+    predicate: F,
+}
+
+impl<S, F> Filter<S, F> {
+    adapter_accessors!();
+}
+
+impl<S, F> PointSink for Filter<S, F>
+where
+    S: PointSink,
+    F: FnMut(&Point) -> bool,
+{
+    type Error = S::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        if (self.predicate)(&point) {
+            self.sink.send(point)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        let predicate = &mut self.predicate;
+        let batch = batch
+            .tagged_points()
+            .filter(|p| predicate(p))
+            .collect::<Batch>();
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.sink.send_batch(&batch)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.sink.flush()
+    }
+}
+
+/// Sink returned by [`PointSink::map`].
+#[derive(Debug, Clone)]
+pub struct Map<S, F> {
+    sink: S,
+    f: F,
+}
+
+impl<S, F> Map<S, F> {
+    adapter_accessors!();
+}
+
+impl<S, F> PointSink for Map<S, F>
+where
+    S: PointSink,
+    F: FnMut(Point) -> Point,
+{
+    type Error = S::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.sink.send((self.f)(point))
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        let batch = batch.tagged_points().map(&mut self.f).collect::<Batch>();
+        self.sink.send_batch(&batch)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.sink.flush()
+    }
+}
+
+/// Sink returned by [`PointSink::tee`].
+///
+/// Points are sent to both sinks even if the first one fails, the first error is returned.
+///
+/// Sending is not all-or-nothing: when one sink fails, the other one has the points already.
+/// [`Buffer`] in front of `Tee` sends the failed batch again to both sinks, so the sink that
+/// succeeded gets the points twice. Buffer each sink separately to avoid duplicates, e.g.
+/// `a.buffer(n).tee(b.buffer(n))`.
+#[derive(Debug, Clone)]
+pub struct Tee<S, O> {
+    sink: S,
+    other: O,
+}
+
+impl<S, O> Tee<S, O> {
+    adapter_accessors!();
+
+    pub fn other(&self) -> &O {
+        &self.other
+    }
+}
+
+impl<S, O> PointSink for Tee<S, O>
+where
+    S: PointSink,
+    O: PointSink<Error = S::Error>,
+{
+    type Error = S::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        let first = self.sink.send(point.clone());
+        let second = self.other.send(point);
+        first.and(second)
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        let first = self.sink.send_batch(batch);
+        let second = self.other.send_batch(batch);
+        first.and(second)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let first = self.sink.flush();
+        let second = self.other.flush();
+        first.and(second)
+    }
+}
+
+/// Sink returned by [`PointSink::buffer`].
+#[derive(Debug, Clone)]
+pub struct Buffer<S> {
+    sink: S,
+    batch: Batch,
+    capacity: usize,
+}
+
+impl<S> Buffer<S> {
+    adapter_accessors!();
+
+    /// Points waiting for next batch.
+    pub fn buffered(&self) -> &Batch {
+        &self.batch
+    }
+}
+
+impl<S: PointSink> Buffer<S> {
+    fn send_full(&mut self) -> Result<(), S::Error> {
+        if self.batch.len() >= self.capacity {
+            self.send_buffered()
+        } else {
+            Ok(())
+        }
+    }
+
+    // buffer is cleared only after downstream accepted the batch
+    fn send_buffered(&mut self) -> Result<(), S::Error> {
+        self.sink.send_batch(&self.batch)?;
+        self.batch = Batch::with_capacity(self.capacity);
+        Ok(())
+    }
+}
+
+impl<S: PointSink> PointSink for Buffer<S> {
+    type Error = S::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.batch.push_point(point);
+        self.send_full()
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        self.batch.extend(batch.tagged_points());
+        self.send_full()
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        if !self.batch.is_empty() {
+            self.send_buffered()?;
+        }
+        self.sink.flush()
+    }
+}
+
+/// Sink returned by [`PointSink::with_default_tags`].
+#[derive(Debug, Clone)]
+pub struct WithDefaultTags<S> {
+    sink: S,
+    tags: TagSet,
+}
+
+impl<S> WithDefaultTags<S> {
+    adapter_accessors!();
+
+    pub fn default_tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+impl<S: PointSink> PointSink for WithDefaultTags<S> {
+    type Error = S::Error;
+
+    fn send(&mut self, mut point: Point) -> Result<(), Self::Error> {
+        point.add_default_tags(&self.tags);
+        self.sink.send(point)
+    }
+
+    /// Default tags of `batch` have priority over tags of this sink.
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        let tags = &self.tags;
+        let batch = batch
+            .tagged_points()
+            .map(|mut p| {
+                p.add_default_tags(tags);
+                p
+            })
+            .collect::<Batch>();
+        self.sink.send_batch(&batch)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.sink.flush()
+    }
+}
+
+impl PointSink for Batch {
+    type Error = Infallible;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.push_point(point);
+        Ok(())
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        self.extend(batch.tagged_points());
+        Ok(())
+    }
+}
+
+impl PointSink for Vec<Point> {
+    type Error = Infallible;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.push(point);
+        Ok(())
+    }
+}
+
+impl PointSink for EncodedBatch {
+    type Error = Infallible;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.push_point(point);
+        Ok(())
+    }
+}
+
+impl PointSink for UdpWriter {
+    type Error = io::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.send_batch(&Batch::from(point))
+    }
+
+    /// Lines too large for one datagram are an error, other lines are sent.
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        let report = self.write(batch)?;
+        if report.oversized.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} lines don't fit into datagram", report.oversized.len()),
+            ))
+        }
+    }
+}
+
+impl<C: Connect> PointSink for StreamWriter<C> {
+    type Error = io::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.write(&Batch::from(point))
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        self.write(batch)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        StreamWriter::flush(self)
+    }
+}
+
+#[cfg(feature = "disk-queue")]
+impl PointSink for DiskQueue {
+    type Error = io::Error;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.push(&Batch::from(point)).map(|_| ())
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        self.push(batch).map(|_| ())
+    }
+}
+
+#[cfg(feature = "client-blocking")]
+impl PointSink for InfluxWriter {
+    type Error = WriteError;
+
+    /// Every point is one request, use [`buffer`](PointSink::buffer) to send batches.
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.write(&Batch::from(point))
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        self.write(batch)
+    }
+}
+
+#[cfg(feature = "testing")]
+impl PointSink for RecordingSink {
+    type Error = Infallible;
+
+    fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+        self.record(Some(point));
+        Ok(())
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+        self.record(batch.tagged_points());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.record_flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{named_point, point};
+    use std::convert::TryFrom;
+
+    fn tag(key: &str, value: &str) -> Tag {
+        Tag::try_from((key, value)).unwrap()
+    }
+
+    fn lines(batch: &Batch) -> Vec<String> {
+        batch
+            .to_line_protocol_lossy(None)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn combinators() {
+        let mut debug = vec![];
+        let mut sink = Batch::default()
+            .tee(&mut debug)
+            .map(|p| {
+                Point::builder("renamed")
+                    .unwrap()
+                    .add_tags(p.tags().to_vec())
+                    .add_fields(p.fields().to_vec())
+                    .timestamp(p.timestamp())
+                    .build()
+                    .unwrap()
+            })
+            .with_default_tags(vec![tag("host", "a")])
+            .filter(|p| p.measurement().as_ref() != "skip")
+            .buffer(2);
+
+        sink.send(named_point("cpu", 1)).unwrap();
+        assert_eq!(sink.buffered().len(), 1);
+        sink.send(named_point("skip", 2)).unwrap();
+        assert!(sink.buffered().is_empty());
+        let batch = Batch::from(vec![named_point("cpu", 3), named_point("skip", 4)])
+            .with_default_tags(vec![tag("host", "b"), tag("dc", "x")]);
+        sink.send_batch(&batch).unwrap();
+        assert!(sink.buffered().is_empty());
+        sink.send(named_point("mem", 5)).unwrap();
+        sink.flush().unwrap();
+
+        let tee = sink.into_inner().into_inner().into_inner().into_inner();
+        let collected = tee.get_ref();
+        assert_eq!(
+            lines(collected),
+            vec![
+                "renamed,host=a v=1i 1",
+                "renamed,dc=x,host=b v=3i 3",
+                "renamed,host=a v=5i 5",
+            ]
+        );
+        assert_eq!(debug.len(), 3);
+    }
+
+    #[test]
+    fn filter_sees_default_tags() {
+        let mut sink =
+            Batch::default().filter(|p| p.tags().iter().any(|t| t.key().as_ref() == "host"));
+        let batch = Batch::from(vec![named_point("a", 1), named_point("b", 2)])
+            .with_default_tags(vec![tag("host", "x")]);
+        sink.send_batch(&batch).unwrap();
+        sink.send(named_point("c", 3)).unwrap();
+        assert_eq!(
+            lines(sink.get_ref()),
+            vec!["a,host=x v=1i 1", "b,host=x v=2i 2"]
+        );
+    }
+
+    #[test]
+    fn combinators_flatten_default_tags() {
+        let mut sink = Batch::default()
+            .map(|p| p)
+            .with_default_tags(vec![tag("host", "a")]);
+        let batch = Batch::from(vec![point(1)]).with_default_tags(vec![tag("dc", "x")]);
+        sink.send_batch(&batch).unwrap();
+        let collected = sink.get_ref().get_ref();
+        assert!(collected.default_tags().is_empty());
+        assert_eq!(lines(collected), vec!["m,dc=x,host=a v=1i 1"]);
+    }
+
+    #[test]
+    fn buffer_keeps_points_on_error() {
+        struct FailOnce {
+            failed: bool,
+            batch: Batch,
+        }
+
+        impl PointSink for FailOnce {
+            type Error = &'static str;
+
+            fn send(&mut self, point: Point) -> Result<(), Self::Error> {
+                self.send_batch(&Batch::from(point))
+            }
+
+            fn send_batch(&mut self, batch: &Batch) -> Result<(), Self::Error> {
+                if !self.failed {
+                    self.failed = true;
+                    return Err("down");
+                }
+                self.batch.extend(batch.tagged_points());
+                Ok(())
+            }
+        }
+
+        let mut sink = FailOnce {
+            failed: false,
+            batch: Batch::default(),
+        }
+        .buffer(2);
+        sink.send(point(1)).unwrap();
+        assert_eq!(sink.send(point(2)), Err("down"));
+        assert_eq!(sink.buffered().len(), 2);
+        sink.send(point(3)).unwrap();
+        assert!(sink.buffered().is_empty());
+        assert_eq!(
+            lines(&sink.get_ref().batch),
+            vec!["m v=1i 1", "m v=2i 2", "m v=3i 3"]
+        );
+
+        let mut sink = FailOnce {
+            failed: false,
+            batch: Batch::default(),
+        }
+        .buffer(10);
+        sink.send(point(1)).unwrap();
+        assert_eq!(sink.flush(), Err("down"));
+        sink.flush().unwrap();
+        assert_eq!(lines(&sink.get_ref().batch), vec!["m v=1i 1"]);
+    }
+
+    #[test]
+    fn dyn_sink() {
+        let mut sinks: Vec<Box<dyn PointSink<Error = Infallible>>> =
+            vec![Box::new(Batch::default()), Box::new(EncodedBatch::new())];
+        for sink in &mut sinks {
+            sink.send_batch(&Batch::from(point(1)).with_default_tags(vec![tag("a", "b")]))
+                .unwrap();
+            sink.flush().unwrap();
+        }
+
+        fn send_to(mut sink: impl PointSink<Error = Infallible>) {
+            let batch = Batch::from(point(1)).with_default_tags(vec![tag("a", "b")]);
+            sink.send_batch(&batch).unwrap();
+        }
+        let mut encoded = EncodedBatch::new();
+        send_to(&mut encoded);
+        assert_eq!(encoded.as_str(), "m,a=b v=1i 1");
+    }
+}
@@ -0,0 +1,42 @@
+use super::{FieldKey, FieldType, Measurement, Precision};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Summary of [`Batch`] content returned by [`Batch::stats`].
+///
+/// [`Batch`]:crate::Batch
+/// [`Batch::stats`]:crate::Batch::stats
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BatchStats {
+    /// Number of points.
+    pub points: usize,
+    /// Exact length in bytes of [`Batch::to_line_protocol_lossy(None)`] output.
+    ///
+    /// [`Batch::to_line_protocol_lossy(None)`]:crate::Batch::to_line_protocol_lossy
+    pub encoded_len: usize,
+    /// Number of distinct series as written, with default tags of batch.
+    pub series: usize,
+    /// Field keys with all types of their values seen for each measurement.
+    ///
+    /// More than one type for a single field key means the server will reject some points.
+    pub fields: BTreeMap<Measurement, BTreeMap<FieldKey, BTreeSet<FieldType>>>,
+    /// Lowest timestamp in nanoseconds. Points with [`Timestamp::Now`] are skipped.
+    ///
+    /// [`Timestamp::Now`]:crate::Timestamp::Now
+    pub min_timestamp: Option<i64>,
+    /// Highest timestamp in nanoseconds. Points with [`Timestamp::Now`] are skipped.
+    ///
+    /// [`Timestamp::Now`]:crate::Timestamp::Now
+    pub max_timestamp: Option<i64>,
+    /// Number of points for each timestamp precision. `None` counts points with
+    /// [`Timestamp::Now`].
+    ///
+    /// [`Timestamp::Now`]:crate::Timestamp::Now
+    pub precisions: BTreeMap<Option<Precision>, usize>,
+}
+
+impl BatchStats {
+    /// Number of distinct measurements.
+    pub fn measurements(&self) -> usize {
+        self.fields.len()
+    }
+}
@@ -0,0 +1,484 @@
+//! Writers streaming newline terminated line protocol over TCP or Unix domain sockets.
+
+use super::{Batch, EncodedBatch, Precision, RetryPolicy};
+use std::{
+    io::{self, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+#[cfg(unix)]
+use std::{os::unix::net::UnixStream, path::PathBuf};
+
+/// Opens new connection of [`StreamWriter`].
+pub trait Connect {
+    type Stream: Write;
+
+    fn connect(&self) -> io::Result<Self::Stream>;
+}
+
+/// Connects to the first reachable address.
+#[derive(Debug, Clone)]
+pub struct TcpConnector {
+    addrs: Vec<SocketAddr>,
+    timeout: Duration,
+    write_timeout: Option<Duration>,
+}
+
+impl TcpConnector {
+    pub fn new(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            addrs: addr.to_socket_addrs()?.collect(),
+            timeout: Duration::from_secs(5),
+            write_timeout: None,
+        })
+    }
+
+    /// Timeout of one connection attempt. Default is 5 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Timeout of writes on opened connection, so stalled receiver doesn't block writer forever.
+    /// Default is no timeout.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+}
+
+impl Connect for TcpConnector {
+    type Stream = TcpStream;
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_err = None;
+        for addr in &self.addrs {
+            match TcpStream::connect_timeout(addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_write_timeout(self.write_timeout)?;
+                    return Ok(stream);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")
+        }))
+    }
+}
+
+/// Connects to Unix domain socket at path.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct UnixConnector {
+    path: PathBuf,
+    write_timeout: Option<Duration>,
+}
+
+#[cfg(unix)]
+impl UnixConnector {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            write_timeout: None,
+        }
+    }
+
+    /// Timeout of writes on opened connection. Default is no timeout.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+}
+
+#[cfg(unix)]
+impl Connect for UnixConnector {
+    type Stream = UnixStream;
+
+    fn connect(&self) -> io::Result<UnixStream> {
+        let stream = UnixStream::connect(&self.path)?;
+        stream.set_write_timeout(self.write_timeout)?;
+        Ok(stream)
+    }
+}
+
+/// Behavior of [`StreamWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamConfig {
+    /// Precision timestamps are converted to. It must match precision configured on receiver.
+    pub precision: Precision,
+    /// Send lines on every write. Otherwise lines are sent when `buffer_bytes` are queued or on
+    /// [`flush`](StreamWriter::flush).
+    pub flush_on_write: bool,
+    pub buffer_bytes: usize,
+    /// Lines kept while disconnected. Oldest lines are dropped when queue is full.
+    pub max_queue_bytes: usize,
+    /// Delay between reconnect attempts. Only backoff settings are used.
+    pub reconnect: RetryPolicy,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            precision: Precision::Nanos,
+            flush_on_write: true,
+            buffer_bytes: 64 * 1024,
+            max_queue_bytes: 8 * 1024 * 1024,
+            reconnect: RetryPolicy {
+                initial_backoff: Duration::from_millis(100),
+                max_backoff: Duration::from_secs(30),
+                jitter: Duration::from_millis(100),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Writer streaming line protocol to Telegraf `socket_listener` or similar input.
+///
+/// Connection is opened on first write. When it fails or breaks, lines are queued (up to
+/// [`StreamConfig::max_queue_bytes`]) and new connection is tried on following writes, not sooner
+/// than reconnect backoff allows. Writes never wait for backoff.
+#[derive(Debug)]
+pub struct StreamWriter<C: Connect> {
+    connector: C,
+    config: StreamConfig,
+    stream: Option<C::Stream>,
+    // newline terminated lines not sent yet
+    queue: String,
+    failures: usize,
+    next_attempt: Option<Instant>,
+    dropped_lines: usize,
+}
+
+/// Writer of TCP stream.
+pub type TcpWriter = StreamWriter<TcpConnector>;
+
+/// Writer of Unix domain socket stream.
+#[cfg(unix)]
+pub type UnixStreamWriter = StreamWriter<UnixConnector>;
+
+impl StreamWriter<TcpConnector> {
+    /// Writer connecting to `addr`. Address is resolved now, connection is opened on first write.
+    pub fn new(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self::with_connector(TcpConnector::new(addr)?))
+    }
+}
+
+#[cfg(unix)]
+impl StreamWriter<UnixConnector> {
+    /// Writer connecting to socket at `path` on first write.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_connector(UnixConnector::new(path))
+    }
+}
+
+impl<C: Connect> StreamWriter<C> {
+    pub fn with_connector(connector: C) -> Self {
+        Self {
+            connector,
+            config: StreamConfig::default(),
+            stream: None,
+            queue: String::new(),
+            failures: 0,
+            next_attempt: None,
+            dropped_lines: 0,
+        }
+    }
+
+    pub fn with_config(mut self, config: StreamConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+
+    /// Queue `batch` and send it if [`StreamConfig::flush_on_write`] is set.
+    ///
+    /// Failed connection is not an error, lines stay queued. See [`flush`](StreamWriter::flush).
+    pub fn write(&mut self, batch: &Batch) -> io::Result<()> {
+        for point in batch {
+            point.write_text(
+                &mut self.queue,
+                Some(self.config.precision),
+                batch.default_tags(),
+            );
+            self.queue.push('\n');
+        }
+        self.after_write()
+    }
+
+    /// Queue lines of `encoded`. Its precision should match receiver.
+    pub fn write_encoded(&mut self, encoded: &EncodedBatch) -> io::Result<()> {
+        if !encoded.is_empty() {
+            self.queue += encoded.as_str();
+            self.queue.push('\n');
+        }
+        self.after_write()
+    }
+
+    /// Send all queued lines, connecting if needed and allowed by backoff.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.queue.is_empty() {
+            return Ok(());
+        }
+        if self.stream.is_none() {
+            self.reconnect()?;
+        }
+        let stream = self.stream.as_mut().expect("connected");
+        let mut sent = 0;
+        let result = loop {
+            match stream.write(&self.queue.as_bytes()[sent..]) {
+                Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    sent += n;
+                    if sent == self.queue.len() {
+                        break stream.flush();
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => break Err(err),
+            }
+        };
+
+        if let Err(err) = result {
+            // line cut in the middle is broken on receiver, it is not sent again
+            let mut end = sent;
+            if sent > 0 && self.queue.as_bytes()[sent - 1] != b'\n' {
+                end = self.queue.as_bytes()[sent..]
+                    .iter()
+                    .position(|b| *b == b'\n')
+                    .map_or(self.queue.len(), |i| sent + i + 1);
+                self.dropped_lines += 1;
+            }
+            self.queue.drain(..end);
+            self.disconnect();
+            return Err(err);
+        }
+        self.queue.clear();
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Bytes of lines waiting to be sent.
+    pub fn queued_bytes(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Number of lines dropped because queue was full or connection broke in the middle of line.
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped_lines
+    }
+
+    fn after_write(&mut self) -> io::Result<()> {
+        self.trim_queue();
+        if self.config.flush_on_write || self.queue.len() >= self.config.buffer_bytes {
+            match self.flush() {
+                Err(err) if is_connection_error(&err) => Ok(()),
+                result => result,
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reconnect(&mut self) -> io::Result<()> {
+        if let Some(next) = self.next_attempt {
+            if Instant::now() < next {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "waiting before reconnect",
+                ));
+            }
+        }
+        match self.connector.connect() {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.failures = 0;
+                self.next_attempt = None;
+                Ok(())
+            }
+            Err(err) => {
+                self.disconnect();
+                Err(err)
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.failures += 1;
+        let delay = self.config.reconnect.backoff(self.failures);
+        self.next_attempt = Some(Instant::now() + delay);
+    }
+
+    // drop the oldest whole lines to fit into `max_queue_bytes`
+    fn trim_queue(&mut self) {
+        if self.queue.len() <= self.config.max_queue_bytes {
+            return;
+        }
+        let mut end = 0;
+        while self.queue.len() - end > self.config.max_queue_bytes {
+            end = match self.queue[end..].find('\n') {
+                Some(i) => end + i + 1,
+                None => self.queue.len(),
+            };
+            self.dropped_lines += 1;
+        }
+        self.queue.drain(..end);
+    }
+}
+
+fn is_connection_error(err: &io::Error) -> bool {
+    !matches!(
+        err.kind(),
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::point;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+    };
+
+    fn batch(from: i64, to: i64) -> Batch {
+        (from..to).map(point).collect()
+    }
+
+    fn config() -> StreamConfig {
+        StreamConfig {
+            precision: Precision::Secs,
+            reconnect: RetryPolicy {
+                initial_backoff: Duration::from_millis(0),
+                jitter: Duration::from_millis(0),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn read_lines(stream: impl io::Read, count: usize) -> Vec<String> {
+        BufReader::new(stream)
+            .lines()
+            .take(count)
+            .map(|l| l.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn tcp_reconnect_and_queue() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut writer = TcpWriter::new(listener.local_addr().unwrap())
+            .unwrap()
+            .with_config(StreamConfig {
+                max_queue_bytes: 18,
+                reconnect: RetryPolicy {
+                    initial_backoff: Duration::from_secs(3600),
+                    jitter: Duration::from_millis(0),
+                    ..Default::default()
+                },
+                ..config()
+            });
+        writer.write(&batch(0, 1)).unwrap();
+        assert!(writer.is_connected());
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(read_lines(stream, 1), vec!["m v=0i 0"]);
+
+        // server closed connection, it is noticed on one of following writes
+        for _ in 0..100 {
+            if !writer.is_connected() {
+                break;
+            }
+            writer.write(&batch(1, 2)).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!writer.is_connected());
+        assert_eq!(writer.queued_bytes(), 9);
+
+        // only two lines of 9 bytes fit into queue
+        writer.write(&batch(2, 5)).unwrap();
+        assert!(!writer.is_connected());
+        assert_eq!(writer.queued_bytes(), 18);
+        assert_eq!(writer.dropped_lines(), 2);
+        assert!(writer.flush().is_err());
+
+        // skip reconnect backoff
+        writer.next_attempt = None;
+        writer.flush().unwrap();
+        assert!(writer.is_connected());
+        assert_eq!(writer.queued_bytes(), 0);
+
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(read_lines(stream, 2), vec!["m v=3i 3", "m v=4i 4"]);
+    }
+
+    #[test]
+    fn write_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpConnector::new(listener.local_addr().unwrap())
+            .unwrap()
+            .write_timeout(Duration::from_secs(1))
+            .connect()
+            .unwrap();
+        assert_eq!(
+            stream.write_timeout().unwrap(),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn buffer_until_flush() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut writer = TcpWriter::new(listener.local_addr().unwrap())
+            .unwrap()
+            .with_config(StreamConfig {
+                flush_on_write: false,
+                buffer_bytes: 20,
+                ..config()
+            });
+        writer.write(&batch(0, 2)).unwrap();
+        assert!(!writer.is_connected());
+        assert_eq!(writer.queued_bytes(), 18);
+
+        let encoded = batch(2, 3).into_iter().collect::<EncodedBatch>();
+        writer.write_encoded(&encoded).unwrap();
+        assert_eq!(writer.queued_bytes(), 0);
+        writer.write(&batch(3, 4)).unwrap();
+        writer.flush().unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(
+            read_lines(stream, 4),
+            vec!["m v=0i 0", "m v=1i 1", "m v=2i 2", "m v=3i 3"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_stream() {
+        use std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("telegraf.sock");
+        let mut writer = UnixStreamWriter::new(&path).with_config(config());
+        writer.write(&batch(0, 1)).unwrap();
+        assert_eq!(writer.queued_bytes(), 9);
+
+        let listener = UnixListener::bind(&path).unwrap();
+        writer.write(&batch(1, 2)).unwrap();
+        assert!(writer.is_connected());
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(read_lines(stream, 2), vec!["m v=0i 0", "m v=1i 1"]);
+    }
+}
@@ -0,0 +1,9 @@
+//! Locking of state shared between threads.
+
+use std::sync::{Mutex, MutexGuard};
+
+/// Lock `mutex` even if it is poisoned. State guarded by the crate's mutexes stays consistent
+/// even if other thread panicked while holding the lock, so the panic is not propagated.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
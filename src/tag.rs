@@ -6,8 +6,8 @@ use super::{
 };
 use derive_more::{Deref, Display};
 
-use std::borrow::Borrow;
 use std::convert::{TryFrom, TryInto};
+use std::{borrow::Borrow, sync::Arc};
 
 #[cfg(feature = "serde")]
 use serde1::{de::Error as DeserializeError, Deserialize, Deserializer, Serialize, Serializer};
@@ -17,14 +17,15 @@ use serde1::{de::Error as DeserializeError, Deserialize, Deserializer, Serialize
 /// Tag keys are strings and they store metadata.
 /// Tag keys are indexed so queries on tag keys are performant.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash, Display, Deref)]
-pub struct TagKey(String);
+#[deref(forward)]
+pub struct TagKey(Arc<str>);
 
 impl TagKey {
     /// Create Tag with check for correctness.
     pub fn new(s: impl Into<String>) -> Result<Self, Error> {
         let s = s.into();
         prevent_key(&s)?;
-        Ok(Self(s))
+        Ok(Self(s.into()))
     }
 }
 
@@ -34,7 +35,7 @@ impl Serialize for TagKey {
     where
         S: Serializer,
     {
-        self.0.serialize(serializer)
+        serializer.serialize_str(&self.0)
     }
 }
 
@@ -78,7 +79,8 @@ impl AsRef<str> for TagKey {
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash, Display, Deref)]
-pub struct TagValue(String);
+#[deref(forward)]
+pub struct TagValue(Arc<str>);
 
 #[cfg(feature = "serde")]
 impl Serialize for TagValue {
@@ -86,7 +88,7 @@ impl Serialize for TagValue {
     where
         S: Serializer,
     {
-        self.0.serialize(serializer)
+        serializer.serialize_str(&self.0)
     }
 }
 
@@ -101,11 +103,25 @@ impl<'de> Deserialize<'de> for TagValue {
     }
 }
 
+impl Borrow<str> for TagValue {
+    #[inline]
+    fn borrow(&self) -> &str {
+        self.0.borrow()
+    }
+}
+
+impl AsRef<str> for TagValue {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
 impl TagValue {
     pub fn new(s: impl Into<String>) -> Result<Self, Error> {
         let s = s.into();
         prevent_tag_value(&s)?;
-        Ok(Self(s))
+        Ok(Self(s.into()))
     }
 }
 
@@ -127,7 +143,7 @@ impl TryFrom<&str> for TagValue {
 ///
 /// Tags are an optional part of the data structure, but they are useful for storing commonly-queried metadata;
 /// tags are indexed so queries on tags are performant.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 pub struct Tag {
     key: TagKey,
     value: TagValue,
@@ -158,9 +174,25 @@ impl Tag {
         })
     }
 
+    pub(crate) fn from_parts(key: TagKey, value: TagValue) -> Self {
+        Self { key, value }
+    }
+
+    pub fn key(&self) -> &TagKey {
+        &self.key
+    }
+
+    pub fn value(&self) -> &TagValue {
+        &self.value
+    }
+
     pub(crate) fn to_text(&self) -> String {
         let escaped_key = escape::tag_key(&self.key);
         let escaped_value = escape::tag_value(&self.value);
         format!("{}={}", escaped_key, escaped_value)
     }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        escape::key_len(&self.key) + 1 + escape::key_len(&self.value)
+    }
 }
@@ -0,0 +1,170 @@
+use super::{error::Error, escape, number, Field, Measurement, Tag, TagSet, Timestamp};
+use std::{
+    borrow::Borrow,
+    convert::{TryFrom, TryInto},
+};
+
+/// Measurement and tag set shared by many points, validated and escaped once.
+///
+/// Use it when only fields and timestamp change between points:
+/// ```
+/// # use influxdb_line_protocol::{Field, PointTemplate, Timestamp};
+/// let template = PointTemplate::new("cpu", vec![("host", "a"), ("core", "0")]).unwrap();
+/// let mut buf = String::new();
+/// let fields = vec![Field::new("load", 0.5).unwrap()];
+/// template.write(&fields, Timestamp::Secs(1), &mut buf).unwrap();
+/// assert_eq!(buf, "cpu,core=0,host=a load=0.5 1");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointTemplate {
+    measurement: Measurement,
+    tag_set: TagSet,
+    // escaped measurement and tag set
+    prefix: String,
+}
+
+impl PointTemplate {
+    pub fn new<I>(measurement: impl Into<String>, tags: I) -> Result<Self, Error>
+    where
+        I: IntoIterator,
+        I::Item: TryInto<Tag>,
+        <I::Item as TryInto<Tag>>::Error: Into<Error>,
+    {
+        let measurement = Measurement::new(measurement)?;
+        let mut tag_set = tags
+            .into_iter()
+            .map(|t| t.try_into().map_err(|e| e.into()))
+            .collect::<Result<TagSet, Error>>()?;
+        // the same order as in `PointBuilder::build`
+        tag_set.sort();
+        Ok(Self::from_parts(measurement, tag_set))
+    }
+
+    fn from_parts(measurement: Measurement, tag_set: TagSet) -> Self {
+        let mut prefix = escape::measurement(&measurement);
+        for tag in &tag_set {
+            prefix.push(',');
+            prefix += &tag.to_text();
+        }
+
+        Self {
+            measurement,
+            tag_set,
+            prefix,
+        }
+    }
+
+    /// Template with `default_tags` whose keys are not in tag set, like lines of batch with
+    /// default tags.
+    pub(crate) fn with_default_tags(&self, default_tags: &[Tag]) -> Self {
+        let mut tag_set = self.tag_set.clone();
+        tag_set.extend(
+            default_tags
+                .iter()
+                .filter(|d| self.tag_set.iter().all(|t| t.key() != d.key()))
+                .cloned(),
+        );
+        tag_set.sort();
+        Self::from_parts(self.measurement.clone(), tag_set)
+    }
+
+    pub fn measurement(&self) -> &Measurement {
+        &self.measurement
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tag_set
+    }
+
+    /// Escaped measurement and tag set.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Append line with `fields` and `timestamp` to `buf`.
+    ///
+    /// Returns [`Error::NoField`] and writes nothing if `fields` is empty.
+    pub fn write<I>(
+        &self,
+        fields: I,
+        timestamp: impl Into<Timestamp>,
+        buf: &mut String,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Field>,
+    {
+        let start = buf.len();
+        buf.push_str(&self.prefix);
+
+        let mut separator = ' ';
+        for field in fields {
+            buf.push(separator);
+            separator = ',';
+            field.borrow().write_text(buf);
+        }
+        if separator == ' ' {
+            buf.truncate(start);
+            return Err(Error::NoField);
+        }
+
+        if let Some(v) = timestamp.into().value() {
+            buf.push(' ');
+            number::push_integer(buf, v);
+        }
+        Ok(())
+    }
+}
+
+impl<M, I> TryFrom<(M, I)> for PointTemplate
+where
+    M: Into<String>,
+    I: IntoIterator,
+    I::Item: TryInto<Tag>,
+    <I::Item as TryInto<Tag>>::Error: Into<Error>,
+{
+    type Error = Error;
+    fn try_from(v: (M, I)) -> Result<Self, Self::Error> {
+        Self::new(v.0, v.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point;
+
+    #[test]
+    fn same_as_point() {
+        let tags = vec![("host", "a b"), ("core", "0,1")];
+        let fields = vec![
+            Field::new("load", 0.5).unwrap(),
+            Field::new("msg", "x=\"y\"").unwrap(),
+        ];
+        let point = Point::builder("cpu load")
+            .unwrap()
+            .try_add_tags(tags.clone())
+            .add_fields(fields.clone())
+            .timestamp(Timestamp::Milli(10))
+            .build()
+            .unwrap();
+
+        let template = PointTemplate::new("cpu load", tags).unwrap();
+        let mut buf = String::new();
+        template
+            .write(&fields, Timestamp::Milli(10), &mut buf)
+            .unwrap();
+        assert_eq!(buf, point.to_text_with_precision(None));
+        assert_eq!(template.prefix(), r#"cpu\ load,core=0\,1,host=a\ b"#);
+    }
+
+    #[test]
+    fn no_fields() {
+        let template = PointTemplate::new("cpu", Vec::<(&str, &str)>::new()).unwrap();
+        let mut buf = String::from("previous");
+        assert!(template
+            .write(Vec::<Field>::new(), Timestamp::Now, &mut buf)
+            .is_err());
+        assert_eq!(buf, "previous");
+    }
+}
@@ -0,0 +1,18 @@
+//! Fixtures shared by tests of several modules.
+
+use super::{Point, Timestamp};
+
+/// Point `m v=<v>i <v>` with timestamp in seconds.
+pub(crate) fn point(v: i64) -> Point {
+    named_point("m", v)
+}
+
+/// Like [`point`] with other measurement.
+pub(crate) fn named_point(measurement: &str, v: i64) -> Point {
+    Point::builder(measurement)
+        .unwrap()
+        .try_add_field(("v", v))
+        .timestamp(Timestamp::Secs(v))
+        .build()
+        .unwrap()
+}
@@ -0,0 +1,847 @@
+//! In-process stand-in for InfluxDB write API, for integration tests of writers.
+
+use super::{compress, error::Error, sync, Point, Precision};
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// default `max-body-size` of InfluxDB
+const MAX_BODY_BYTES: usize = 25_000_000;
+// too large body is read and discarded up to this size before answering `413`, so the client
+// gets the response instead of connection reset
+const MAX_DRAIN_BYTES: usize = 4 * MAX_BODY_BYTES;
+
+/// Scripted reply to one write request, queued with [`MockInfluxServer::enqueue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockResponse {
+    reply: Reply,
+    delay: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reply {
+    Accept,
+    Reject {
+        lines: Vec<usize>,
+        reason: String,
+    },
+    Status {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: String,
+    },
+}
+
+impl MockResponse {
+    /// Write like InfluxDB does: valid lines are recorded, invalid ones are reported in
+    /// `400` partial write error. This is the reply when no response is queued.
+    pub fn accept() -> Self {
+        Self::from_reply(Reply::Accept)
+    }
+
+    /// Reply with `status` and `body` without recording any point.
+    pub fn status(status: u16, body: impl Into<String>) -> Self {
+        Self::from_reply(Reply::Status {
+            status,
+            headers: vec![],
+            body: body.into(),
+        })
+    }
+
+    /// `429` with optional `Retry-After` in whole seconds.
+    pub fn too_many_requests(retry_after: Option<Duration>) -> Self {
+        Self::status(429, r#"{"code":"too many requests","message":"slow down"}"#)
+            .retry_after(retry_after)
+    }
+
+    /// `503` with optional `Retry-After` in whole seconds.
+    pub fn service_unavailable(retry_after: Option<Duration>) -> Self {
+        Self::status(
+            503,
+            r#"{"code":"unavailable","message":"service unavailable"}"#,
+        )
+        .retry_after(retry_after)
+    }
+
+    /// Reject `lines` (counted from 1) of payload with `reason` and record the rest, answering
+    /// with `400` partial write error in format of the endpoint.
+    pub fn partial_write(
+        lines: impl IntoIterator<Item = usize>,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self::from_reply(Reply::Reject {
+            lines: lines.into_iter().collect(),
+            reason: reason.into(),
+        })
+    }
+
+    /// Add response header. Only replies created with [`status`](MockResponse::status) and its
+    /// shortcuts have custom headers.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        if let Reply::Status { headers, .. } = &mut self.reply {
+            headers.push((name.into(), value.into()));
+        }
+        self
+    }
+
+    /// Wait before answering. The request is recorded before waiting, like a server that wrote
+    /// points but answered too late.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn from_reply(reply: Reply) -> Self {
+        Self {
+            reply,
+            delay: Duration::from_secs(0),
+        }
+    }
+
+    fn retry_after(self, retry_after: Option<Duration>) -> Self {
+        match retry_after {
+            Some(d) => self.header("Retry-After", d.as_secs().to_string()),
+            None => self,
+        }
+    }
+}
+
+impl Default for MockResponse {
+    fn default() -> Self {
+        Self::accept()
+    }
+}
+
+/// Request received by [`MockInfluxServer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path without query.
+    pub path: String,
+    /// Decoded query parameters.
+    pub query: Vec<(String, String)>,
+    /// Headers with lowercase names.
+    pub headers: Vec<(String, String)>,
+    /// Decompressed body.
+    pub body: String,
+    /// Status server answered with.
+    pub status: u16,
+}
+
+impl RecordedRequest {
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Value of header `name`, case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    responses: VecDeque<MockResponse>,
+    requests: Vec<RecordedRequest>,
+    points: Vec<Point>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        sync::lock(&self.state)
+    }
+
+    fn record(&self, request: RecordedRequest, points: Vec<Point>) {
+        let mut state = self.lock();
+        state.requests.push(request);
+        state.points.extend(points);
+        self.changed.notify_all();
+    }
+}
+
+/// HTTP server on localhost answering `/api/v2/write`, `/write` (v1) and `/ping`.
+///
+/// Written points are parsed back into [`Point`]s and recorded. Write requests consume
+/// responses queued with [`enqueue`](MockInfluxServer::enqueue), so failures and slow replies
+/// can be scripted. Bodies compressed with algorithms of enabled features are decompressed.
+/// Bodies larger than 25MB, the default limit of InfluxDB, are answered with `413` and recorded
+/// without body. Server stops when dropped.
+///
+/// ```
+/// use influxdb_line_protocol::{MockInfluxServer, MockResponse};
+/// use std::{io::{Read, Write}, net::TcpStream, time::Duration};
+///
+/// let server = MockInfluxServer::start().unwrap();
+/// server.enqueue(MockResponse::service_unavailable(Some(Duration::from_secs(1))));
+///
+/// let body = "cpu value=1 1";
+/// for _ in 0..2 {
+///     let mut stream = TcpStream::connect(server.addr()).unwrap();
+///     write!(stream, "POST /api/v2/write?org=o&bucket=b HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+///     stream.read_to_end(&mut vec![]).unwrap();
+/// }
+///
+/// let statuses = server.requests().iter().map(|r| r.status).collect::<Vec<_>>();
+/// assert_eq!(statuses, vec![503, 204]);
+/// assert_eq!(server.points().len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct MockInfluxServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockInfluxServer {
+    /// Listen on a free port of `127.0.0.1`.
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let shared = shared.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let shared = shared.clone();
+                        // connections are served in parallel, so a delayed reply doesn't block
+                        // others
+                        thread::spawn(move || {
+                            let _ = serve(stream, &shared);
+                        });
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            addr,
+            shared,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Base url, for example `http://127.0.0.1:34567`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Queue `response` for next write request without one.
+    pub fn enqueue(&self, response: MockResponse) -> &Self {
+        self.shared.lock().responses.push_back(response);
+        self
+    }
+
+    /// Number of queued responses not used yet.
+    pub fn pending_responses(&self) -> usize {
+        self.shared.lock().responses.len()
+    }
+
+    /// Points recorded so far, in order of arrival.
+    pub fn points(&self) -> Vec<Point> {
+        self.shared.lock().points.clone()
+    }
+
+    /// Take recorded points, leaving none.
+    pub fn take_points(&self) -> Vec<Point> {
+        std::mem::take(&mut self.shared.lock().points)
+    }
+
+    /// All received requests, including `/ping` and failed ones.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.shared.lock().requests.clone()
+    }
+
+    /// Wait until at least `count` points are recorded. Returns `false` on timeout.
+    pub fn wait_for_points(&self, count: usize, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        while state.points.len() < count {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        true
+    }
+
+    /// Forget recorded requests, points and queued responses.
+    pub fn reset(&self) {
+        *self.shared.lock() = State::default();
+    }
+}
+
+impl Drop for MockInfluxServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // wake up blocked `accept`
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn into_recorded(self, body: String, status: u16) -> RecordedRequest {
+        RecordedRequest {
+            method: self.method,
+            path: self.path,
+            query: self.query,
+            headers: self.headers,
+            body,
+            status,
+        }
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    fn error(status: u16, code: &str, message: &str) -> Self {
+        Self::new(
+            status,
+            serde_json::json!({ "code": code, "message": message }).to_string(),
+        )
+    }
+}
+
+fn serve(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request = match read_request(&mut reader)? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    let (response, delay) = match read_body(&mut reader, &request)? {
+        Some(body) => {
+            request.body = body;
+            respond(request, shared)
+        }
+        None => {
+            let response = Response::error(
+                413,
+                "request too large",
+                "unable to read data: points batch is too large",
+            );
+            shared.record(
+                request.into_recorded(String::new(), response.status),
+                vec![],
+            );
+            (response, Duration::from_secs(0))
+        }
+    };
+    if delay > Duration::from_secs(0) {
+        thread::sleep(delay);
+    }
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.body.len()
+    );
+    if !response.body.is_empty() {
+        head += "Content-Type: application/json; charset=utf-8\r\n";
+    }
+    for (name, value) in &response.headers {
+        head += &format!("{}: {}\r\n", name, value);
+    }
+    head += "\r\n";
+    let stream = reader.get_mut();
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+// `None` when connection was closed before request line, body is read by `read_body`
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(invalid_data("invalid request line")),
+    };
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+    let query = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let path = path.to_string();
+
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(i) = line.find(':') {
+            headers.push((
+                line[..i].trim().to_ascii_lowercase(),
+                line[i + 1..].trim().to_string(),
+            ));
+        }
+    }
+    Ok(Some(Request {
+        method,
+        path,
+        query,
+        headers,
+        body: vec![],
+    }))
+}
+
+// `None` when body is larger than `MAX_BODY_BYTES`, it is discarded up to `MAX_DRAIN_BYTES`
+fn read_body(reader: &mut impl BufRead, request: &Request) -> io::Result<Option<Vec<u8>>> {
+    if request
+        .header("transfer-encoding")
+        .is_some_and(|v| v.eq_ignore_ascii_case("chunked"))
+    {
+        return read_chunked(reader);
+    }
+    let len = match request.header("content-length") {
+        Some(len) => len
+            .parse::<usize>()
+            .map_err(|_| invalid_data("invalid content length"))?,
+        None => return Ok(Some(vec![])),
+    };
+    if len > MAX_BODY_BYTES {
+        discard(reader, len.min(MAX_DRAIN_BYTES))?;
+        return Ok(None);
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut body = vec![];
+    let mut len = 0usize;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let size = line.trim().split(';').next().unwrap_or_default();
+        let size =
+            usize::from_str_radix(size, 16).map_err(|_| invalid_data("invalid chunk size"))?;
+        if size == 0 {
+            // skip trailers
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                    return Ok(Some(body).filter(|_| len <= MAX_BODY_BYTES));
+                }
+            }
+        }
+        len = len.saturating_add(size);
+        if len > MAX_DRAIN_BYTES {
+            return Ok(None);
+        }
+        if len > MAX_BODY_BYTES {
+            body = vec![];
+            discard(reader, size)?;
+        } else {
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+        }
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+}
+
+fn discard(reader: &mut impl BufRead, len: usize) -> io::Result<()> {
+    let copied = io::copy(&mut io::Read::take(reader, len as u64), &mut io::sink())?;
+    if copied < len as u64 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn respond(request: Request, shared: &Shared) -> (Response, Duration) {
+    let mut delay = Duration::from_secs(0);
+    let mut points = vec![];
+    let mut body = String::new();
+    let response = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/ping") | ("HEAD", "/ping") => {
+            let mut response = Response::new(204, "");
+            response
+                .headers
+                .push(("X-Influxdb-Version".into(), "mock".into()));
+            response
+        }
+        ("POST", path @ "/api/v2/write") | ("POST", path @ "/write") => {
+            let v1 = path == "/write";
+            match decode_write(&request, v1) {
+                Ok((text, precision)) => {
+                    let scripted = shared.lock().responses.pop_front().unwrap_or_default();
+                    delay = scripted.delay;
+                    let response = write(&text, precision, scripted.reply, v1, &mut points);
+                    body = text;
+                    response
+                }
+                Err(response) => response,
+            }
+        }
+        _ => Response::error(404, "not found", "path not found"),
+    };
+
+    shared.record(request.into_recorded(body, response.status), points);
+    (response, delay)
+}
+
+// decompressed body and precision of write request
+fn decode_write(request: &Request, v1: bool) -> Result<(String, Precision), Response> {
+    let precision = match request.query_param("precision") {
+        None | Some("") => Precision::Nanos,
+        Some("n") if v1 => Precision::Nanos,
+        Some("u") if v1 => Precision::Micro,
+        Some(p) => p.parse().map_err(|_| {
+            Response::error(
+                400,
+                "invalid",
+                &format!("invalid precision `{}`, expected ns, us, ms or s", p),
+            )
+        })?,
+    };
+    let body = compress::decompress(
+        request.header("content-encoding").unwrap_or_default(),
+        &request.body,
+    )
+    .map_err(|err| match err.kind() {
+        io::ErrorKind::InvalidInput => {
+            Response::error(415, "unsupported media type", &err.to_string())
+        }
+        _ => Response::error(400, "invalid", &err.to_string()),
+    })?;
+    let body = String::from_utf8(body)
+        .map_err(|_| Response::error(400, "invalid", "body is not valid UTF-8"))?;
+    Ok((body, precision))
+}
+
+fn write(
+    text: &str,
+    precision: Precision,
+    reply: Reply,
+    v1: bool,
+    out: &mut Vec<Point>,
+) -> Response {
+    let (rejected_lines, reason) = match reply {
+        Reply::Status {
+            status,
+            headers,
+            body,
+        } => {
+            return Response {
+                status,
+                headers,
+                body,
+            }
+        }
+        Reply::Accept => (vec![], String::new()),
+        Reply::Reject { lines, reason } => (lines, reason),
+    };
+
+    // (line number, line, reason)
+    let mut errors = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if rejected_lines.contains(&number) {
+            errors.push((number, line, reason.clone()));
+            continue;
+        }
+        match Point::parse_line(trimmed, precision) {
+            Ok(point) => out.push(point),
+            Err(Error::Parse(reason)) => errors.push((number, line, reason)),
+            Err(err) => errors.push((number, line, err.to_string())),
+        }
+    }
+    if errors.is_empty() {
+        return Response::new(204, "");
+    }
+
+    if v1 {
+        let message = errors
+            .iter()
+            .map(|(_, line, reason)| format!("unable to parse '{}': {}", line, reason))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let message = format!("partial write: {} dropped={}", message, errors.len());
+        Response::new(400, serde_json::json!({ "error": message }).to_string())
+    } else {
+        let mut message = "partial write has occurred, errors encountered on line(s):".to_string();
+        for (number, _, reason) in &errors {
+            message += &format!("\nerror parsing line {} (1-based): {}", number, reason);
+        }
+        Response::error(400, "invalid", &message)
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::WriteError, Timestamp};
+    use std::io::Read;
+
+    // raw HTTP client, so tests don't depend on client features
+    fn post(server: &MockInfluxServer, target: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            target,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        (status, response)
+    }
+
+    fn response_body(response: &str) -> &str {
+        &response[response.find("\r\n\r\n").unwrap() + 4..]
+    }
+
+    #[test]
+    fn record_points() {
+        let server = MockInfluxServer::start().unwrap();
+        let (status, _) = post(
+            &server,
+            "/api/v2/write?org=my%20org&bucket=b&precision=s",
+            "cpu,host=a value=1 10\n\ncpu,host=b value=2i 20\n",
+        );
+        assert_eq!(status, 204);
+        let (status, _) = post(&server, "/write?db=telegraf&precision=u", "mem free=3u 30");
+        assert_eq!(status, 204);
+        let (status, response) = post(&server, "/api/v2/write", "cpu value=1\ncpu value\n");
+        assert_eq!(status, 400);
+        match WriteError::from_response(status, response_body(&response), None) {
+            Some(err @ WriteError::PartialWrite { .. }) => {
+                assert_eq!(err.line_errors()[0].line, Some(2))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream.write_all(b"GET /ping HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+
+        let timestamps = server
+            .points()
+            .iter()
+            .map(|p| p.timestamp())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timestamps,
+            vec![
+                Timestamp::Secs(10),
+                Timestamp::Secs(20),
+                Timestamp::Micro(30),
+                Timestamp::Now
+            ]
+        );
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].query_param("org"), Some("my org"));
+        assert_eq!(requests[1].path, "/write");
+        assert_eq!(requests[1].body, "mem free=3u 30");
+        assert_eq!(requests[3].path, "/ping");
+        assert!(server.wait_for_points(4, Duration::from_secs(0)));
+        assert!(!server.wait_for_points(5, Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn body_too_large() {
+        let server = MockInfluxServer::start().unwrap();
+        let body = vec![b'a'; MAX_BODY_BYTES + 1];
+        for chunked in &[false, true] {
+            let mut stream = TcpStream::connect(server.addr()).unwrap();
+            let head = if *chunked {
+                format!("Transfer-Encoding: chunked\r\n\r\n{:x}\r\n", body.len())
+            } else {
+                format!("Content-Length: {}\r\n\r\n", body.len())
+            };
+            write!(stream, "POST /api/v2/write HTTP/1.1\r\n{}", head).unwrap();
+            stream.write_all(&body).unwrap();
+            if *chunked {
+                stream.write_all(b"\r\n0\r\n\r\n").unwrap();
+            }
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        }
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|r| r.status == 413 && r.body.is_empty()));
+        assert!(server.points().is_empty());
+        let (status, _) = post(&server, "/api/v2/write", "m v=1");
+        assert_eq!(status, 204);
+    }
+
+    #[test]
+    fn scripted_failures() {
+        let server = MockInfluxServer::start().unwrap();
+        server
+            .enqueue(MockResponse::too_many_requests(Some(Duration::from_secs(
+                2,
+            ))))
+            .enqueue(MockResponse::service_unavailable(None))
+            .enqueue(MockResponse::partial_write(vec![2], "field type conflict"))
+            .enqueue(MockResponse::accept().delay(Duration::from_millis(100)));
+        let body = "m v=1i 1\nm v=2i 2\nm v=3i 3";
+
+        let (status, response) = post(&server, "/api/v2/write", body);
+        assert_eq!(status, 429);
+        assert!(response.contains("\r\nRetry-After: 2\r\n"));
+        assert_eq!(post(&server, "/api/v2/write", body).0, 503);
+        assert!(server.points().is_empty());
+
+        let (status, response) = post(&server, "/write", body);
+        let err = WriteError::from_response(status, response_body(&response), None).unwrap();
+        assert_eq!(
+            err.line_errors()[0].original_line.as_deref(),
+            Some("m v=2i 2")
+        );
+        assert_eq!(err.line_errors()[0].reason, "field type conflict");
+        assert_eq!(server.take_points().len(), 2);
+
+        let start = Instant::now();
+        assert_eq!(post(&server, "/api/v2/write", body).0, 204);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(server.points().len(), 3);
+        assert_eq!(server.pending_responses(), 0);
+
+        server.reset();
+        assert!(server.requests().is_empty());
+    }
+
+    #[cfg(feature = "client-blocking")]
+    #[test]
+    fn retry_influx_writer() {
+        use crate::{test_util::point, Batch, InfluxWriter, RetryPolicy, WriteTarget};
+
+        let server = MockInfluxServer::start().unwrap();
+        server
+            .enqueue(MockResponse::service_unavailable(None))
+            .enqueue(MockResponse::too_many_requests(Some(Duration::from_secs(
+                0,
+            ))));
+        let writer = InfluxWriter::new(WriteTarget::new(server.url(), "org", "bucket"))
+            .retry_policy(RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                jitter: Duration::from_millis(0),
+                ..Default::default()
+            });
+        let batch = (0..3).map(point).collect::<Batch>();
+        writer.write(&batch).unwrap();
+
+        assert_eq!(server.requests().len(), 3);
+        assert_eq!(server.points(), batch.iter().cloned().collect::<Vec<_>>());
+    }
+}
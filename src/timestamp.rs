@@ -15,6 +15,25 @@ pub enum Timestamp {
 }
 
 impl Timestamp {
+    /// Create timestamp from `value` with given precision. `None` precision means [`Timestamp::Now`].
+    pub(crate) fn from_precision(precision: Option<Precision>, value: i64) -> Self {
+        match precision {
+            None => Self::Now,
+            Some(Precision::Nanos) => Self::Nanos(value),
+            Some(Precision::Micro) => Self::Micro(value),
+            Some(Precision::Milli) => Self::Milli(value),
+            Some(Precision::Secs) => Self::Secs(value),
+        }
+    }
+
+    /// Raw value of timestamp in its own precision.
+    pub(crate) fn value(self) -> Option<i64> {
+        match self {
+            Self::Now => None,
+            Self::Nanos(v) | Self::Micro(v) | Self::Milli(v) | Self::Secs(v) => Some(v),
+        }
+    }
+
     pub fn precision(self) -> Option<Precision> {
         match self {
             Self::Now => None,
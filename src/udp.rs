@@ -0,0 +1,226 @@
+//! Fire-and-forget writer sending line protocol in UDP datagrams.
+
+use super::{error::UdpWriteError, Batch, EncodedBatch, Precision};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// Result of one [`UdpWriter`] write.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UdpReport {
+    /// Number of sent datagrams.
+    pub datagrams: usize,
+    /// Number of sent bytes.
+    pub bytes: usize,
+    /// Lines not sent because they don't fit into one datagram.
+    pub oversized: Vec<String>,
+}
+
+/// Writer sending points to InfluxDB 1.x UDP service or Telegraf `socket_listener`.
+///
+/// Lines are packed into datagrams of at most [`mtu`](UdpWriter::mtu) bytes. A line is never
+/// split between datagrams, lines longer than `mtu` are reported in [`UdpReport::oversized`].
+/// Delivery is not confirmed. Writing stops at the first send error, which carries report of
+/// datagrams sent before it.
+#[derive(Debug)]
+pub struct UdpWriter {
+    socket: UdpSocket,
+    mtu: usize,
+    precision: Precision,
+}
+
+impl UdpWriter {
+    /// Bind to any local port and connect to `addr`.
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")
+        })?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(Self::from_socket(socket))
+    }
+
+    /// Use already connected `socket`.
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            mtu: 1400,
+            precision: Precision::Nanos,
+        }
+    }
+
+    /// Maximum payload of one datagram. Default is 1400 bytes, which fits into Ethernet frame.
+    pub fn mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// Precision timestamps are converted to. It must match precision configured on receiver,
+    /// default is nanoseconds.
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    pub fn write(&self, batch: &Batch) -> Result<UdpReport, UdpWriteError> {
+        let lines = batch
+            .iter()
+            .map(|p| p.to_text_with_default_tags(Some(self.precision), batch.default_tags()));
+        self.send_lines(lines)
+    }
+
+    /// Send lines of `encoded`. Its precision should match receiver.
+    pub fn write_encoded(&self, encoded: &EncodedBatch) -> Result<UdpReport, UdpWriteError> {
+        self.send_lines(encoded.lines())
+    }
+
+    fn send_lines<I>(&self, lines: I) -> Result<UdpReport, UdpWriteError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let mut report = UdpReport::default();
+        let mut datagram = String::with_capacity(self.mtu);
+        for line in lines {
+            let line = line.as_ref();
+            if line.len() > self.mtu {
+                report.oversized.push(line.to_string());
+                continue;
+            }
+            let separator = usize::from(!datagram.is_empty());
+            if datagram.len() + separator + line.len() > self.mtu {
+                report = self.send(&datagram, report)?;
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(line);
+        }
+        if !datagram.is_empty() {
+            report = self.send(&datagram, report)?;
+        }
+        Ok(report)
+    }
+
+    fn send(&self, datagram: &str, mut report: UdpReport) -> Result<UdpReport, UdpWriteError> {
+        match self.socket.send(datagram.as_bytes()) {
+            Ok(sent) => {
+                report.datagrams += 1;
+                report.bytes += sent;
+                Ok(report)
+            }
+            Err(error) => Err(UdpWriteError { error, report }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::point, Point, Timestamp};
+    use std::time::Duration;
+
+    fn receiver() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    fn receive(socket: &UdpSocket, count: usize) -> Vec<String> {
+        let mut buf = [0; 2048];
+        (0..count)
+            .map(|_| {
+                let len = socket.recv(&mut buf).unwrap();
+                String::from_utf8(buf[..len].to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pack_lines_into_datagrams() {
+        let receiver = receiver();
+        let writer = UdpWriter::connect(receiver.local_addr().unwrap())
+            .unwrap()
+            .mtu(40)
+            .precision(Precision::Secs);
+
+        // every line has 8 bytes, the last one 44
+        let mut batch = (0..5).map(point).collect::<Batch>();
+        batch.push_point(
+            Point::builder("m")
+                .unwrap()
+                .try_add_field(("x".repeat(37).as_str(), 5i64))
+                .timestamp(Timestamp::Secs(5))
+                .build()
+                .unwrap(),
+        );
+        let report = writer.write(&batch).unwrap();
+        assert_eq!(report.datagrams, 2);
+        assert_eq!(report.oversized, vec![format!("m {}=5i 5", "x".repeat(37))]);
+
+        let datagrams = receive(&receiver, 2);
+        assert_eq!(
+            datagrams,
+            vec!["m v=0i 0\nm v=1i 1\nm v=2i 2\nm v=3i 3", "m v=4i 4"]
+        );
+        assert_eq!(
+            report.bytes,
+            datagrams.iter().map(|d| d.len()).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn partial_report_on_error() {
+        let receiver = receiver();
+        let writer = UdpWriter::connect(receiver.local_addr().unwrap())
+            .unwrap()
+            .mtu(100_000);
+        let line = |i: i64, len: usize| {
+            Point::builder("m")
+                .unwrap()
+                .try_add_field(("s", "x".repeat(len).as_str()))
+                .timestamp(Timestamp::Secs(i))
+                .build()
+                .unwrap()
+        };
+        // the second datagram is longer than UDP allows
+        let batch = Batch::from(vec![line(0, 60_000), line(1, 40_000), line(2, 30_000)]);
+        let err = writer.write(&batch).unwrap_err();
+        assert_eq!(err.report.datagrams, 1);
+        assert_eq!(err.report.bytes, 60_008);
+        assert!(err.report.oversized.is_empty());
+    }
+
+    #[test]
+    fn write_encoded() {
+        let receiver = receiver();
+        let writer = UdpWriter::connect(receiver.local_addr().unwrap())
+            .unwrap()
+            .mtu(8);
+        let encoded = (0..3).map(point).collect::<EncodedBatch>();
+        let report = writer.write_encoded(&encoded).unwrap();
+        assert_eq!(report.datagrams, 3);
+        assert!(report.oversized.is_empty());
+        assert_eq!(
+            receive(&receiver, 3),
+            vec!["m v=0i 0", "m v=1i 1", "m v=2i 2"]
+        );
+        assert_eq!(
+            writer.write(&Batch::default()).unwrap(),
+            UdpReport::default()
+        );
+    }
+}